@@ -0,0 +1,482 @@
+use std::fmt::{self, Display};
+
+use crate::expression::{Expression, Operator};
+use crate::parser::LexItem;
+use crate::span::{Position, Span};
+use crate::value::Value;
+
+/// Something the parser would have accepted at the point where it failed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Expected {
+    Expression,
+    BinaryOperator,
+    Variable,
+    OpenParen,
+    CloseParen,
+    Comma,
+    Arrow,
+    Func,
+    Apply,
+    If,
+    Then,
+    Else,
+    Let,
+    Rec,
+    In,
+    Equals,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum LexError {
+    /// A character that does not start any token.
+    UnexpectedCharacter {
+        found: char,
+        span: Span,
+        position: Position,
+    },
+    /// An integer literal too large to be represented. Never raised with the `bigint` feature.
+    IntegerOutOfRange {
+        literal: String,
+        span: Span,
+        position: Position,
+    },
+    /// A float literal too large to be represented.
+    FloatOutOfRange {
+        literal: String,
+        span: Span,
+        position: Position,
+    },
+    /// A block comment that is still open at the end of the input.
+    UnterminatedComment { span: Span, position: Position },
+    /// A string literal that is still open at the end of the input.
+    UnterminatedString { span: Span, position: Position },
+    /// A backslash in a string literal that does not start a known escape.
+    InvalidEscape {
+        escape: String,
+        span: Span,
+        position: Position,
+    },
+}
+
+impl LexError {
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::UnexpectedCharacter { span, .. }
+            | LexError::IntegerOutOfRange { span, .. }
+            | LexError::FloatOutOfRange { span, .. }
+            | LexError::UnterminatedComment { span, .. }
+            | LexError::UnterminatedString { span, .. }
+            | LexError::InvalidEscape { span, .. } => *span,
+        }
+    }
+
+    pub fn position(&self) -> Position {
+        match self {
+            LexError::UnexpectedCharacter { position, .. }
+            | LexError::IntegerOutOfRange { position, .. }
+            | LexError::FloatOutOfRange { position, .. }
+            | LexError::UnterminatedComment { position, .. }
+            | LexError::UnterminatedString { position, .. }
+            | LexError::InvalidEscape { position, .. } => *position,
+        }
+    }
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexError::UnexpectedCharacter {
+                found, position, ..
+            } => write!(f, "{}: unexpected character '{}'", position, found),
+            LexError::IntegerOutOfRange {
+                literal, position, ..
+            } => write!(
+                f,
+                "{}: integer literal {} is out of range",
+                position, literal
+            ),
+            LexError::FloatOutOfRange {
+                literal, position, ..
+            } => write!(f, "{}: float literal {} is out of range", position, literal),
+            LexError::UnterminatedComment { position, .. } => {
+                write!(f, "{}: unterminated block comment", position)
+            }
+            LexError::UnterminatedString { position, .. } => {
+                write!(f, "{}: unterminated string literal", position)
+            }
+            LexError::InvalidEscape {
+                escape, position, ..
+            } => write!(
+                f,
+                "{}: invalid escape '{}' in string literal",
+                position, escape
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LexError {}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ParseError {
+    /// The input could not be split into tokens.
+    Lex(LexError),
+    /// A token was found where none of the `expected` tokens was.
+    UnexpectedToken {
+        found: LexItem,
+        span: Span,
+        position: Position,
+        expected: Vec<Expected>,
+    },
+    /// The input ended while one of the `expected` tokens was still required.
+    UnexpectedEof {
+        offset: usize,
+        position: Position,
+        expected: Vec<Expected>,
+    },
+    /// A complete expression was followed by more tokens.
+    TrailingInput {
+        found: LexItem,
+        span: Span,
+        position: Position,
+    },
+}
+
+impl ParseError {
+    /// The source range the error points at. End-of-input errors have an empty span.
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::UnexpectedToken { span, .. } | ParseError::TrailingInput { span, .. } => {
+                *span
+            }
+            ParseError::UnexpectedEof { offset, .. } => Span::new(*offset, *offset),
+            ParseError::Lex(error) => error.span(),
+        }
+    }
+
+    pub fn position(&self) -> Position {
+        match self {
+            ParseError::UnexpectedToken { position, .. }
+            | ParseError::UnexpectedEof { position, .. }
+            | ParseError::TrailingInput { position, .. } => *position,
+            ParseError::Lex(error) => error.position(),
+        }
+    }
+
+    pub fn expected(&self) -> &[Expected] {
+        match self {
+            ParseError::UnexpectedToken { expected, .. }
+            | ParseError::UnexpectedEof { expected, .. } => expected,
+            ParseError::TrailingInput { .. } | ParseError::Lex(_) => &[],
+        }
+    }
+}
+
+impl Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expected::Expression => write!(f, "expression"),
+            Expected::BinaryOperator => write!(f, "binary operator"),
+            Expected::Variable => write!(f, "variable name"),
+            Expected::OpenParen => write!(f, "'('"),
+            Expected::CloseParen => write!(f, "')'"),
+            Expected::Comma => write!(f, "','"),
+            Expected::Arrow => write!(f, "'=>'"),
+            Expected::Func => write!(f, "'func'"),
+            Expected::Apply => write!(f, "'apply'"),
+            Expected::If => write!(f, "'if'"),
+            Expected::Then => write!(f, "'then'"),
+            Expected::Else => write!(f, "'else'"),
+            Expected::Let => write!(f, "'let'"),
+            Expected::Rec => write!(f, "'rec'"),
+            Expected::In => write!(f, "'in'"),
+            Expected::Equals => write!(f, "'='"),
+        }
+    }
+}
+
+/// Writes `a`, `a or b`, `a, b or c`, ...
+fn write_expected(f: &mut fmt::Formatter<'_>, expected: &[Expected]) -> fmt::Result {
+    for (index, item) in expected.iter().enumerate() {
+        if index > 0 {
+            let separator = if index + 1 == expected.len() {
+                " or "
+            } else {
+                ", "
+            };
+            write!(f, "{}", separator)?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedToken {
+                found,
+                position,
+                expected,
+                ..
+            } => {
+                write!(f, "{}: expected ", position)?;
+                write_expected(f, expected)?;
+                write!(f, ", found '{}'", found)
+            }
+            ParseError::UnexpectedEof {
+                position, expected, ..
+            } => {
+                write!(f, "{}: expected ", position)?;
+                write_expected(f, expected)?;
+                write!(f, ", found end of input")
+            }
+            ParseError::TrailingInput {
+                found, position, ..
+            } => write!(
+                f,
+                "{}: unexpected '{}' after end of expression",
+                position, found
+            ),
+            ParseError::Lex(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// The kind of a value, as reported by evaluation errors.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Type {
+    Integer,
+    /// A fraction, see [`Value::Rational`].
+    Rational,
+    Decimal,
+    Float,
+    Boolean,
+    String,
+    Function,
+    /// An unbound variable, see [`Value::Symbol`].
+    Variable,
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Integer => write!(f, "integer"),
+            Type::Rational => write!(f, "rational"),
+            Type::Decimal => write!(f, "decimal"),
+            Type::Float => write!(f, "float"),
+            Type::Boolean => write!(f, "boolean"),
+            Type::String => write!(f, "string"),
+            Type::Function => write!(f, "function"),
+            Type::Variable => write!(f, "variable"),
+        }
+    }
+}
+
+/// The error returned when converting a [`Value`] to a Rust type it does not hold.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ConversionError {
+    pub expected: Type,
+    pub value: Value,
+}
+
+impl Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "expected {}, found {} '{}'",
+            self.expected,
+            self.value.type_of(),
+            self.value
+        )
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+/// An error raised by [`Expression::eval`]. Each variant carries the
+/// subexpression that caused it, except [`EvalError::UnboundVariable`], which
+/// carries the variable's name, and all carry its [`span`](EvalError::span).
+#[derive(Debug, PartialEq, Clone)]
+pub enum EvalError {
+    /// An operand of `op` evaluated to the wrong kind of value.
+    TypeMismatch {
+        op: Operator,
+        expected: Type,
+        found: Type,
+        expr: Expression,
+        span: Option<Span>,
+    },
+    /// The function position of an `apply` did not evaluate to a function.
+    NotAFunction {
+        found: Type,
+        expr: Expression,
+        span: Option<Span>,
+    },
+    /// A call such as `apply(f, a, b)` supplied `found` arguments, but the
+    /// function returned a non-function after taking `expected` of them.
+    TooManyArguments {
+        expected: usize,
+        found: usize,
+        expr: Expression,
+        span: Option<Span>,
+    },
+    /// The condition of an `if` did not evaluate to a boolean.
+    NonBooleanCondition {
+        found: Type,
+        expr: Expression,
+        span: Option<Span>,
+    },
+    /// The divisor of a division, `%` or `mod` evaluated to zero.
+    DivisionByZero {
+        expr: Expression,
+        span: Option<Span>,
+    },
+    /// The exponent of a `^` or `pow` evaluated to a negative number.
+    NegativeExponent {
+        expr: Expression,
+        span: Option<Span>,
+    },
+    /// A decimal `^` raised a negative number to a fractional power, which
+    /// has no real result.
+    NegativeBase {
+        expr: Expression,
+        span: Option<Span>,
+    },
+    /// The result of `op` does not fit in an integer, fraction or decimal.
+    Overflow {
+        op: Operator,
+        expr: Expression,
+        span: Option<Span>,
+    },
+    /// A number that is too large to convert to a decimal, such as a float literal under
+    /// [`Floats::Decimal`](crate::evaluator::Floats::Decimal).
+    DecimalOutOfRange {
+        expr: Expression,
+        span: Option<Span>,
+    },
+    /// The argument of `parse_int` is not the text of an integer, or one too
+    /// large to be represented.
+    InvalidInteger {
+        text: String,
+        expr: Expression,
+        span: Option<Span>,
+    },
+    /// A variable is bound neither in the expression nor by the caller.
+    UnboundVariable { name: String, span: Option<Span> },
+}
+
+impl EvalError {
+    /// The span of the source text the error came from, if the expression was
+    /// evaluated with [`Evaluator::eval_spanned`](crate::evaluator::Evaluator::eval_spanned).
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            EvalError::TypeMismatch { span, .. }
+            | EvalError::NotAFunction { span, .. }
+            | EvalError::TooManyArguments { span, .. }
+            | EvalError::NonBooleanCondition { span, .. }
+            | EvalError::DivisionByZero { span, .. }
+            | EvalError::NegativeExponent { span, .. }
+            | EvalError::NegativeBase { span, .. }
+            | EvalError::Overflow { span, .. }
+            | EvalError::DecimalOutOfRange { span, .. }
+            | EvalError::InvalidInteger { span, .. }
+            | EvalError::UnboundVariable { span, .. } => *span,
+        }
+    }
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::TypeMismatch {
+                op,
+                expected,
+                found,
+                expr,
+                ..
+            } => write!(
+                f,
+                "'{}' expected {} operand, found {} in '{}'",
+                op, expected, found, expr
+            ),
+            EvalError::NotAFunction { found, expr, .. } => {
+                write!(f, "cannot apply {} '{}' as a function", found, expr)
+            }
+            EvalError::TooManyArguments {
+                expected,
+                found,
+                expr,
+                ..
+            } => write!(
+                f,
+                "too many arguments in '{}': the function takes {} but {} were supplied",
+                expr, expected, found
+            ),
+            EvalError::NonBooleanCondition { found, expr, .. } => {
+                write!(
+                    f,
+                    "'if' condition must be boolean, found {} in '{}'",
+                    found, expr
+                )
+            }
+            EvalError::DivisionByZero { expr, .. } => write!(f, "division by zero in '{}'", expr),
+            EvalError::NegativeExponent { expr, .. } => {
+                write!(f, "negative exponent in '{}'", expr)
+            }
+            EvalError::NegativeBase { expr, .. } => {
+                write!(f, "negative base with a fractional exponent in '{}'", expr)
+            }
+            EvalError::Overflow { op, expr, .. } => {
+                write!(f, "overflow in '{}' evaluating '{}'", op, expr)
+            }
+            EvalError::DecimalOutOfRange { expr, .. } => {
+                write!(f, "'{}' is out of range for a decimal", expr)
+            }
+            EvalError::InvalidInteger { text, expr, .. } => {
+                write!(f, "cannot parse {:?} as an integer in '{}'", text, expr)
+            }
+            EvalError::UnboundVariable { name, .. } => write!(f, "unbound variable '{}'", name),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// Any error produced by [`crate::parse_and_eval`].
+#[derive(Debug, PartialEq, Clone)]
+pub enum Error {
+    Parse(ParseError),
+    Eval(EvalError),
+}
+
+impl From<LexError> for ParseError {
+    fn from(error: LexError) -> Self {
+        ParseError::Lex(error)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Self {
+        Error::Parse(error)
+    }
+}
+
+impl From<EvalError> for Error {
+    fn from(error: EvalError) -> Self {
+        Error::Eval(error)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(error) => write!(f, "{}", error),
+            Error::Eval(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,1418 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use rust_decimal::{Decimal, MathematicalOps};
+
+use crate::error::{EvalError, Type};
+use crate::expression::{BinaryOperator, Builtin, Expression, Operator, UnaryOperator};
+use crate::integer::{self, int, Int};
+use crate::rational::Rational;
+use crate::span::{NodeId, Span, Spans};
+use crate::value::{Bindings, Closure, Env, Value};
+
+/// How integer operations behave when the result does not fit in an `i64`.
+/// Division by zero is an error in every mode. With the `bigint` feature every
+/// result fits, so the modes behave the same, except that a power too large to
+/// compute is an [`EvalError::Overflow`] in every mode.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Arithmetic {
+    /// Overflow is an [`EvalError::Overflow`].
+    #[default]
+    Checked,
+    /// Overflow wraps around at the bounds of `i64`.
+    Wrapping,
+    /// Overflow clamps to `i64::MIN` or `i64::MAX`.
+    Saturating,
+}
+
+impl Arithmetic {
+    // Raises `base` to the power `exp` by repeated squaring, returning None on
+    // overflow, or if the result would be too large even for a big integer.
+    // Only squares that end up in the result are computed, so a square
+    // overflows only if the result does.
+    fn pow(self, base: &Int, exp: u64) -> Option<Int> {
+        if !integer::power_in_bounds(base, exp) {
+            return None;
+        }
+        let mut result = int(1);
+        let mut base = integer::dup(base);
+        let mut exp = exp;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = self.apply(BinaryOperator::Multiply, &result, &base)?;
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = self.apply(BinaryOperator::Multiply, &base, &base)?;
+            }
+        }
+        Some(result)
+    }
+}
+
+#[cfg(not(feature = "bigint"))]
+impl Arithmetic {
+    // Negates an integer, returning None on overflow
+    pub(crate) fn negate(self, a: &Int) -> Option<Int> {
+        match self {
+            Arithmetic::Checked => a.checked_neg(),
+            Arithmetic::Wrapping => Some(a.wrapping_neg()),
+            Arithmetic::Saturating => Some(a.saturating_neg()),
+        }
+    }
+
+    // Finds the greatest common divisor of `a` and `b`, returning None on
+    // overflow. Only gcd(i64::MIN, 0) and gcd(i64::MIN, i64::MIN) do not fit.
+    pub(crate) fn gcd(self, a: &Int, b: &Int) -> Option<Int> {
+        let (mut a, mut b) = (a.unsigned_abs(), b.unsigned_abs());
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        match self {
+            Arithmetic::Checked => i64::try_from(a).ok(),
+            Arithmetic::Wrapping => Some(a as i64),
+            Arithmetic::Saturating => Some(i64::try_from(a).unwrap_or(i64::MAX)),
+        }
+    }
+
+    // Applies an integer-valued operator, returning None on overflow
+    pub(crate) fn apply(self, op: BinaryOperator, a: &Int, b: &Int) -> Option<Int> {
+        let (a, b) = (*a, *b);
+        match self {
+            Arithmetic::Checked => match op {
+                BinaryOperator::Add => a.checked_add(b),
+                BinaryOperator::Subtract => a.checked_sub(b),
+                BinaryOperator::Multiply => a.checked_mul(b),
+                BinaryOperator::Divide => a.checked_div(b),
+                _ => None,
+            },
+            Arithmetic::Wrapping => match op {
+                BinaryOperator::Add => Some(a.wrapping_add(b)),
+                BinaryOperator::Subtract => Some(a.wrapping_sub(b)),
+                BinaryOperator::Multiply => Some(a.wrapping_mul(b)),
+                BinaryOperator::Divide => Some(a.wrapping_div(b)),
+                _ => None,
+            },
+            Arithmetic::Saturating => match op {
+                BinaryOperator::Add => Some(a.saturating_add(b)),
+                BinaryOperator::Subtract => Some(a.saturating_sub(b)),
+                BinaryOperator::Multiply => Some(a.saturating_mul(b)),
+                BinaryOperator::Divide => Some(a.saturating_div(b)),
+                _ => None,
+            },
+        }
+    }
+}
+
+// Big integers never overflow, so these only return None for operators that
+// are not integer-valued
+#[cfg(feature = "bigint")]
+impl Arithmetic {
+    pub(crate) fn negate(self, a: &Int) -> Option<Int> {
+        Some(-a)
+    }
+
+    pub(crate) fn gcd(self, a: &Int, b: &Int) -> Option<Int> {
+        let zero = int(0);
+        let (mut a, mut b) = (a.clone(), b.clone());
+        while b != zero {
+            (a, b) = (b.clone(), a % b);
+        }
+        Some(if a < zero { -a } else { a })
+    }
+
+    pub(crate) fn apply(self, op: BinaryOperator, a: &Int, b: &Int) -> Option<Int> {
+        match op {
+            BinaryOperator::Add => Some(a + b),
+            BinaryOperator::Subtract => Some(a - b),
+            BinaryOperator::Multiply => Some(a * b),
+            BinaryOperator::Divide => Some(a / b),
+            _ => None,
+        }
+    }
+}
+
+/// What dividing one integer by another produces.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Division {
+    /// The quotient is truncated toward zero, so `/(7, 2)` is 3.
+    #[default]
+    Truncating,
+    /// The quotient is exact, a [`Value::Rational`] unless it is a whole
+    /// number, so `/(7, 2)` is 7/2 and `/(8, 2)` is 4.
+    Exact,
+}
+
+/// What float literals such as `2.5` evaluate to.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Floats {
+    /// A binary [`Value::Float`], following IEEE 754: `0.1 + 0.2` is not
+    /// quite 0.3, and dividing by zero gives an infinity or NaN.
+    #[default]
+    Binary,
+    /// A fixed-point [`Value::Decimal`] with up to 28 digits after the point,
+    /// for currency: `0.1 + 0.2` is exactly 0.3, and dividing by zero is an
+    /// error as it is for integers.
+    Decimal,
+}
+
+/// What a variable with no binding evaluates to.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Unbound {
+    /// Unbound variables are an [`EvalError::UnboundVariable`].
+    #[default]
+    Error,
+    /// Unbound variables evaluate to [`Value::Symbol`], leaving them for the caller to interpret.
+    Symbolic,
+}
+
+/// An expression lowered for evaluation, with the span of the source text it
+/// was parsed from, if known. Subexpressions are reference counted so that
+/// closures and pending evaluation steps share them instead of copying them.
+#[derive(Debug)]
+pub(crate) struct Node {
+    pub(crate) kind: NodeKind,
+    pub(crate) span: Option<Span>,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum NodeKind {
+    Integer(Int),
+    Float {
+        value: f64,
+        text: String,
+    },
+    String(String),
+    Variable(String),
+    Boolean(bool),
+    BinaryOp {
+        op: BinaryOperator,
+        lhs: Rc<Node>,
+        rhs: Rc<Node>,
+    },
+    UnaryOp {
+        op: UnaryOperator,
+        child: Rc<Node>,
+    },
+    Func {
+        param: Rc<str>,
+        body: Rc<Node>,
+    },
+    If {
+        condition: Rc<Node>,
+        then_expr: Rc<Node>,
+        else_expr: Rc<Node>,
+    },
+    Apply {
+        func_expr: Rc<Node>,
+        arg_expr: Rc<Node>,
+    },
+    Let {
+        name: Rc<str>,
+        value: Rc<Node>,
+        body: Rc<Node>,
+    },
+    LetRec {
+        name: Rc<str>,
+        param: Rc<str>,
+        func_body: Rc<Node>,
+        body: Rc<Node>,
+    },
+    Call {
+        builtin: Builtin,
+        args: Vec<Rc<Node>>,
+    },
+}
+
+// Spans say where a node came from, not what it is, so equal nodes may have different spans
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl Node {
+    pub(crate) fn lower(expr: &Expression) -> Node {
+        Node::lower_spanned(expr, &Spans::default(), &mut 0)
+    }
+
+    // Lowers `expr`, whose node id is `next_id`, taking the spans of it and its
+    // descendants from `spans` and leaving `next_id` at the id after them.
+    fn lower_spanned(expr: &Expression, spans: &Spans, next_id: &mut NodeId) -> Node {
+        let span = spans.get(*next_id);
+        *next_id += 1;
+        let mut lower = |child: &Expression| Rc::new(Node::lower_spanned(child, spans, next_id));
+        let kind = match expr {
+            Expression::Integer(value) => NodeKind::Integer(integer::dup(value)),
+            Expression::Float { value, text } => NodeKind::Float {
+                value: *value,
+                text: text.clone(),
+            },
+            Expression::String(text) => NodeKind::String(text.clone()),
+            Expression::Variable(name) => NodeKind::Variable(name.clone()),
+            Expression::Boolean(value) => NodeKind::Boolean(*value),
+            Expression::BinaryOp { op, lhs, rhs } => NodeKind::BinaryOp {
+                op: *op,
+                lhs: lower(lhs),
+                rhs: lower(rhs),
+            },
+            Expression::UnaryOp { op, child } => NodeKind::UnaryOp {
+                op: *op,
+                child: lower(child),
+            },
+            Expression::Func { param, body } => NodeKind::Func {
+                param: param.as_str().into(),
+                body: lower(body),
+            },
+            Expression::If {
+                condition,
+                then_expr,
+                else_expr,
+            } => NodeKind::If {
+                condition: lower(condition),
+                then_expr: lower(then_expr),
+                else_expr: lower(else_expr),
+            },
+            Expression::Apply {
+                func_expr,
+                arg_expr,
+            } => NodeKind::Apply {
+                func_expr: lower(func_expr),
+                arg_expr: lower(arg_expr),
+            },
+            Expression::Let { name, value, body } => NodeKind::Let {
+                name: name.as_str().into(),
+                value: lower(value),
+                body: lower(body),
+            },
+            Expression::LetRec {
+                name,
+                param,
+                func_body,
+                body,
+            } => NodeKind::LetRec {
+                name: name.as_str().into(),
+                param: param.as_str().into(),
+                func_body: lower(func_body),
+                body: lower(body),
+            },
+            Expression::Call { builtin, args } => NodeKind::Call {
+                builtin: *builtin,
+                args: args.iter().map(&mut lower).collect(),
+            },
+        };
+        Node { kind, span }
+    }
+
+    pub(crate) fn to_expression(&self) -> Expression {
+        let raise = |child: &Node| Box::new(child.to_expression());
+        match &self.kind {
+            NodeKind::Integer(value) => Expression::Integer(integer::dup(value)),
+            NodeKind::Float { value, text } => Expression::Float {
+                value: *value,
+                text: text.clone(),
+            },
+            NodeKind::String(text) => Expression::String(text.clone()),
+            NodeKind::Variable(name) => Expression::Variable(name.clone()),
+            NodeKind::Boolean(value) => Expression::Boolean(*value),
+            NodeKind::BinaryOp { op, lhs, rhs } => Expression::BinaryOp {
+                op: *op,
+                lhs: raise(lhs),
+                rhs: raise(rhs),
+            },
+            NodeKind::UnaryOp { op, child } => Expression::UnaryOp {
+                op: *op,
+                child: raise(child),
+            },
+            NodeKind::Func { param, body } => Expression::Func {
+                param: param.to_string(),
+                body: raise(body),
+            },
+            NodeKind::If {
+                condition,
+                then_expr,
+                else_expr,
+            } => Expression::If {
+                condition: raise(condition),
+                then_expr: raise(then_expr),
+                else_expr: raise(else_expr),
+            },
+            NodeKind::Apply {
+                func_expr,
+                arg_expr,
+            } => Expression::Apply {
+                func_expr: raise(func_expr),
+                arg_expr: raise(arg_expr),
+            },
+            NodeKind::Let { name, value, body } => Expression::Let {
+                name: name.to_string(),
+                value: raise(value),
+                body: raise(body),
+            },
+            NodeKind::LetRec {
+                name,
+                param,
+                func_body,
+                body,
+            } => Expression::LetRec {
+                name: name.to_string(),
+                param: param.to_string(),
+                func_body: raise(func_body),
+                body: raise(body),
+            },
+            NodeKind::Call { builtin, args } => Expression::Call {
+                builtin: *builtin,
+                args: args.iter().map(|arg| arg.to_expression()).collect(),
+            },
+        }
+    }
+}
+
+/// Evaluates expressions in an environment of variable bindings. Applying a
+/// function binds its parameter in the environment captured by the closure,
+/// so free variables in function bodies resolve lexically.
+///
+/// Evaluation keeps its pending work on the heap rather than the native
+/// stack, so deeply recursive functions are limited by memory alone.
+#[derive(Debug, Clone, Default)]
+pub struct Evaluator {
+    arithmetic: Arithmetic,
+    division: Division,
+    floats: Floats,
+    unbound: Unbound,
+}
+
+// The next thing for the evaluator to do: evaluate a node, or hand a value to
+// the innermost pending frame
+enum Step {
+    Eval(Rc<Node>, Env),
+    Return(Value),
+}
+
+// Work left over while a subexpression is being evaluated, to be finished with its value
+enum Frame {
+    // The value is the operand of the unary operator `node`
+    Unary {
+        node: Rc<Node>,
+    },
+    // The value is the lhs of the binary operator `node`, whose rhs is still
+    // to be evaluated in `env`
+    BinaryLhs {
+        node: Rc<Node>,
+        env: Env,
+    },
+    // The value is the rhs of the binary operator `node`, whose lhs evaluated to `lhs`
+    BinaryRhs {
+        node: Rc<Node>,
+        lhs: Value,
+    },
+    // The value is the function of `node`, whose argument is still to be evaluated in `env`
+    ApplyFunc {
+        node: Rc<Node>,
+        env: Env,
+    },
+    // The value is the argument of `node`, whose function evaluated to `func`
+    ApplyArg {
+        node: Rc<Node>,
+        func: Value,
+    },
+    // The value is the condition of `node`, whose branches are evaluated in `env`
+    IfCondition {
+        node: Rc<Node>,
+        env: Env,
+    },
+    // The value is bound by `node`, whose body is evaluated in `env`
+    LetValue {
+        node: Rc<Node>,
+        env: Env,
+    },
+    // The value is the next argument of the call `node`, whose earlier
+    // arguments evaluated to `values` and whose later ones are evaluated in `env`
+    CallArgs {
+        node: Rc<Node>,
+        env: Env,
+        values: Vec<Value>,
+    },
+}
+
+impl Evaluator {
+    pub fn new() -> Self {
+        Evaluator::default()
+    }
+
+    pub fn with_arithmetic(mut self, arithmetic: Arithmetic) -> Self {
+        self.arithmetic = arithmetic;
+        self
+    }
+
+    pub fn with_division(mut self, division: Division) -> Self {
+        self.division = division;
+        self
+    }
+
+    pub fn with_floats(mut self, floats: Floats) -> Self {
+        self.floats = floats;
+        self
+    }
+
+    pub fn with_unbound(mut self, unbound: Unbound) -> Self {
+        self.unbound = unbound;
+        self
+    }
+
+    pub fn eval(&self, expr: &Expression) -> Result<Value, EvalError> {
+        self.eval_node(Rc::new(Node::lower(expr)), &Env::new(), &Bindings::new())
+    }
+
+    /// Evaluates `expr` with the variables bound in `env` in scope.
+    pub fn eval_in(&self, expr: &Expression, env: &Env) -> Result<Value, EvalError> {
+        self.eval_node(Rc::new(Node::lower(expr)), env, &Bindings::new())
+    }
+
+    /// Evaluates `expr` with the variables in `bindings` in scope. Variables
+    /// bound inside the expression shadow them.
+    pub fn eval_with(&self, expr: &Expression, bindings: &Bindings) -> Result<Value, EvalError> {
+        self.eval_node(Rc::new(Node::lower(expr)), &Env::new(), bindings)
+    }
+
+    /// Evaluates `expr`, which was parsed with the node spans `spans`, so that
+    /// errors carry the span of the source text they came from.
+    pub fn eval_spanned(&self, expr: &Expression, spans: &Spans) -> Result<Value, EvalError> {
+        let node = Node::lower_spanned(expr, spans, &mut 0);
+        self.eval_node(Rc::new(node), &Env::new(), &Bindings::new())
+    }
+
+    fn eval_node(&self, node: Rc<Node>, env: &Env, globals: &Bindings) -> Result<Value, EvalError> {
+        let mut frames: Vec<Frame> = Vec::new();
+        let mut step = Step::Eval(node, env.clone());
+        loop {
+            step = match step {
+                Step::Eval(node, env) => self.eval_step(node, env, globals, &mut frames)?,
+                Step::Return(value) => match frames.pop() {
+                    Some(frame) => self.resume(frame, value, &mut frames)?,
+                    None => return Ok(value),
+                },
+            };
+        }
+    }
+
+    // Starts evaluating `node`, pushing a frame for what is left to do once
+    // the subexpression it evaluates first has a value
+    fn eval_step(
+        &self,
+        node: Rc<Node>,
+        env: Env,
+        globals: &Bindings,
+        frames: &mut Vec<Frame>,
+    ) -> Result<Step, EvalError> {
+        match &node.kind {
+            NodeKind::Integer(value) => Ok(Step::Return(Value::Int(integer::dup(value)))),
+            NodeKind::Float { value, text } => match self.floats {
+                Floats::Binary => Ok(Step::Return(Value::Float(*value))),
+                Floats::Decimal => match decimal_literal(text) {
+                    Some(value) => Ok(Step::Return(Value::Decimal(value))),
+                    None => Err(EvalError::DecimalOutOfRange {
+                        expr: node.to_expression(),
+                        span: node.span,
+                    }),
+                },
+            },
+            NodeKind::String(text) => Ok(Step::Return(Value::String(text.clone()))),
+            NodeKind::Boolean(value) => Ok(Step::Return(Value::Bool(*value))),
+            NodeKind::Variable(name) => match env.lookup(name).or_else(|| globals.get(name)) {
+                Some(value) => Ok(Step::Return(value.clone())),
+                None => match self.unbound {
+                    Unbound::Error => Err(EvalError::UnboundVariable {
+                        name: name.clone(),
+                        span: node.span,
+                    }),
+                    Unbound::Symbolic => Ok(Step::Return(Value::Symbol(name.clone()))),
+                },
+            },
+            NodeKind::Func { param, body } => {
+                // The closure captures the current environment and the parameter
+                Ok(Step::Return(Value::Closure(Closure {
+                    param: param.clone(),
+                    body: body.clone(),
+                    env,
+                    name: None,
+                })))
+            }
+            NodeKind::LetRec {
+                name,
+                param,
+                func_body,
+                body,
+            } => {
+                // The closure binds its own name when applied, rather than capturing
+                // an environment that contains itself
+                let closure = Value::Closure(Closure {
+                    param: param.clone(),
+                    body: func_body.clone(),
+                    env: env.clone(),
+                    name: Some(name.clone()),
+                });
+                Ok(Step::Eval(body.clone(), env.bind(name.clone(), closure)))
+            }
+            NodeKind::UnaryOp { child, .. } => {
+                let child = child.clone();
+                frames.push(Frame::Unary { node });
+                Ok(Step::Eval(child, env))
+            }
+            NodeKind::BinaryOp { lhs, .. } => {
+                let lhs = lhs.clone();
+                frames.push(Frame::BinaryLhs {
+                    node,
+                    env: env.clone(),
+                });
+                Ok(Step::Eval(lhs, env))
+            }
+            NodeKind::Apply { func_expr, .. } => {
+                let func_expr = func_expr.clone();
+                frames.push(Frame::ApplyFunc {
+                    node,
+                    env: env.clone(),
+                });
+                Ok(Step::Eval(func_expr, env))
+            }
+            NodeKind::If { condition, .. } => {
+                let condition = condition.clone();
+                frames.push(Frame::IfCondition {
+                    node,
+                    env: env.clone(),
+                });
+                Ok(Step::Eval(condition, env))
+            }
+            NodeKind::Let { value, .. } => {
+                let value = value.clone();
+                frames.push(Frame::LetValue {
+                    node,
+                    env: env.clone(),
+                });
+                Ok(Step::Eval(value, env))
+            }
+            NodeKind::Call { builtin, args } => match args.first() {
+                Some(first) => {
+                    let first = first.clone();
+                    frames.push(Frame::CallArgs {
+                        node,
+                        env: env.clone(),
+                        values: Vec::new(),
+                    });
+                    Ok(Step::Eval(first, env))
+                }
+                None => self
+                    .apply_builtin(*builtin, args, Vec::new(), &node)
+                    .map(Step::Return),
+            },
+        }
+    }
+
+    // Continues the work in `frame` now that the subexpression it was waiting on evaluated to `value`
+    fn resume(
+        &self,
+        frame: Frame,
+        value: Value,
+        frames: &mut Vec<Frame>,
+    ) -> Result<Step, EvalError> {
+        match frame {
+            Frame::Unary { node } => {
+                let NodeKind::UnaryOp { op, child } = &node.kind else {
+                    unreachable!("unary frames hold unary nodes")
+                };
+                self.apply_unary(*op, child, &node, value).map(Step::Return)
+            }
+            Frame::BinaryLhs { node, env } => {
+                let NodeKind::BinaryOp { rhs, .. } = &node.kind else {
+                    unreachable!("binary frames hold binary nodes")
+                };
+                let rhs = rhs.clone();
+                frames.push(Frame::BinaryRhs { node, lhs: value });
+                Ok(Step::Eval(rhs, env))
+            }
+            Frame::BinaryRhs { node, lhs } => {
+                let NodeKind::BinaryOp {
+                    op,
+                    lhs: lhs_node,
+                    rhs: rhs_node,
+                } = &node.kind
+                else {
+                    unreachable!("binary frames hold binary nodes")
+                };
+                self.apply_binary(*op, (lhs_node, lhs), (rhs_node, value), &node)
+                    .map(Step::Return)
+            }
+            Frame::ApplyFunc { node, env } => {
+                let NodeKind::Apply { arg_expr, .. } = &node.kind else {
+                    unreachable!("apply frames hold apply nodes")
+                };
+                let arg_expr = arg_expr.clone();
+                frames.push(Frame::ApplyArg { node, func: value });
+                Ok(Step::Eval(arg_expr, env))
+            }
+            Frame::ApplyArg { node, func } => {
+                let NodeKind::Apply { func_expr, .. } = &node.kind else {
+                    unreachable!("apply frames hold apply nodes")
+                };
+                // Evaluate the body with the parameter bound in the captured
+                // environment, leaving no frame behind so tail calls run in constant space
+                match func {
+                    Value::Closure(closure) => {
+                        let env = match &closure.name {
+                            Some(name) => closure
+                                .env
+                                .bind(name.clone(), Value::Closure(closure.clone())),
+                            None => closure.env.clone(),
+                        };
+                        let call_env = env.bind(closure.param.clone(), value);
+                        Ok(Step::Eval(closure.body.clone(), call_env))
+                    }
+                    // A curried call whose earlier arguments already used up the function
+                    _ if matches!(func_expr.kind, NodeKind::Apply { .. }) => {
+                        Err(too_many_arguments(&node, frames))
+                    }
+                    _ => Err(EvalError::NotAFunction {
+                        found: func.type_of(),
+                        expr: func_expr.to_expression(),
+                        span: func_expr.span,
+                    }),
+                }
+            }
+            Frame::IfCondition { node, env } => {
+                let NodeKind::If {
+                    condition,
+                    then_expr,
+                    else_expr,
+                } = &node.kind
+                else {
+                    unreachable!("if frames hold if nodes")
+                };
+                match value {
+                    Value::Bool(true) => Ok(Step::Eval(then_expr.clone(), env)),
+                    Value::Bool(false) => Ok(Step::Eval(else_expr.clone(), env)),
+                    _ => Err(EvalError::NonBooleanCondition {
+                        found: value.type_of(),
+                        expr: condition.to_expression(),
+                        span: condition.span,
+                    }),
+                }
+            }
+            Frame::LetValue { node, env } => {
+                // Like applying "func name => body" to the value
+                let NodeKind::Let { name, body, .. } = &node.kind else {
+                    unreachable!("let frames hold let nodes")
+                };
+                Ok(Step::Eval(body.clone(), env.bind(name.clone(), value)))
+            }
+            Frame::CallArgs {
+                node,
+                env,
+                mut values,
+            } => {
+                let NodeKind::Call { builtin, args } = &node.kind else {
+                    unreachable!("call frames hold call nodes")
+                };
+                values.push(value);
+                match args.get(values.len()) {
+                    Some(next) => {
+                        let next = next.clone();
+                        frames.push(Frame::CallArgs {
+                            node,
+                            env: env.clone(),
+                            values,
+                        });
+                        Ok(Step::Eval(next, env))
+                    }
+                    None => self
+                        .apply_builtin(*builtin, args, values, &node)
+                        .map(Step::Return),
+                }
+            }
+        }
+    }
+
+    // Applies `op` to the value of its operand `child`, which belongs to `node`
+    fn apply_unary(
+        &self,
+        op: UnaryOperator,
+        child: &Node,
+        node: &Node,
+        value: Value,
+    ) -> Result<Value, EvalError> {
+        let overflow = || EvalError::Overflow {
+            op: Operator::Unary(op),
+            expr: node.to_expression(),
+            span: node.span,
+        };
+        match op {
+            UnaryOperator::Not => {
+                let b = expect_boolean(Operator::Unary(op), &value, child)?;
+                Ok(Value::Bool(!b))
+            }
+            UnaryOperator::Negate => match value {
+                Value::Float(a) => Ok(Value::Float(-a)),
+                Value::Decimal(a) => Ok(Value::Decimal(-a)),
+                Value::Rational(a) => a.negate().map(Value::from).ok_or_else(overflow),
+                _ => {
+                    let a = expect_integer(Operator::Unary(op), &value, child)?;
+                    self.arithmetic
+                        .negate(&a)
+                        .map(Value::Int)
+                        .ok_or_else(overflow)
+                }
+            },
+        }
+    }
+
+    // Applies `op` to the values of its operands, each paired with the node it came from
+    fn apply_binary(
+        &self,
+        op: BinaryOperator,
+        (lhs, eval_lhs): (&Node, Value),
+        (rhs, eval_rhs): (&Node, Value),
+        node: &Node,
+    ) -> Result<Value, EvalError> {
+        let operator = Operator::Binary(op);
+        let is_string = |value: &Value| matches!(value, Value::String(_));
+        match op {
+            BinaryOperator::Add if is_string(&eval_lhs) || is_string(&eval_rhs) => {
+                let a = expect_string(operator, &eval_lhs, lhs)?;
+                let b = expect_string(operator, &eval_rhs, rhs)?;
+                Ok(Value::String(a + &b))
+            }
+            BinaryOperator::Add
+            | BinaryOperator::Subtract
+            | BinaryOperator::Multiply
+            | BinaryOperator::Divide
+            | BinaryOperator::Remainder
+            | BinaryOperator::Power => {
+                self.apply_arithmetic(op, (lhs, &eval_lhs), (rhs, &eval_rhs), node)
+            }
+            // Equality is structural, so it applies to values of any type, and
+            // values of different types are never equal. Decimals and floats are
+            // the exception: they equal numbers of any type with the same value,
+            // except that NaN equals nothing, not even itself.
+            BinaryOperator::Equals | BinaryOperator::NotEquals => {
+                let equal = match (Domain::of(&eval_lhs), Domain::of(&eval_rhs)) {
+                    (Some(a), Some(b)) if a.max(b) >= Domain::Decimal => self
+                        .compare(operator, (lhs, &eval_lhs), (rhs, &eval_rhs), node)?
+                        .is_some_and(Ordering::is_eq),
+                    _ => eval_lhs == eval_rhs,
+                };
+                Ok(Value::Bool(equal == (op == BinaryOperator::Equals)))
+            }
+            // NaN is unordered, so every comparison with it is false
+            BinaryOperator::LessThan
+            | BinaryOperator::GreaterThan
+            | BinaryOperator::LessEqual
+            | BinaryOperator::GreaterEqual => {
+                let ordering = self.compare(operator, (lhs, &eval_lhs), (rhs, &eval_rhs), node)?;
+                Ok(Value::Bool(ordering.is_some_and(|ordering| match op {
+                    BinaryOperator::LessThan => ordering.is_lt(),
+                    BinaryOperator::GreaterThan => ordering.is_gt(),
+                    BinaryOperator::LessEqual => ordering.is_le(),
+                    _ => ordering.is_ge(),
+                })))
+            }
+            BinaryOperator::And => {
+                let a = expect_boolean(operator, &eval_lhs, lhs)?;
+                let b = expect_boolean(operator, &eval_rhs, rhs)?;
+                Ok(Value::Bool(a && b))
+            }
+            BinaryOperator::Or => {
+                let a = expect_boolean(operator, &eval_lhs, lhs)?;
+                let b = expect_boolean(operator, &eval_rhs, rhs)?;
+                Ok(Value::Bool(a || b))
+            }
+        }
+    }
+
+    // Applies the arithmetic operator `op` in the widest kind of number among
+    // its operands. Exact division works in fractions at least, and fractions
+    // only support `+`, `-`, `*` and `/`.
+    fn apply_arithmetic(
+        &self,
+        op: BinaryOperator,
+        (lhs, eval_lhs): (&Node, &Value),
+        (rhs, eval_rhs): (&Node, &Value),
+        node: &Node,
+    ) -> Result<Value, EvalError> {
+        let operator = Operator::Binary(op);
+        let overflow = || EvalError::Overflow {
+            op: operator,
+            expr: node.to_expression(),
+            span: node.span,
+        };
+        let division_by_zero = || EvalError::DivisionByZero {
+            expr: node.to_expression(),
+            span: node.span,
+        };
+        let mut domain = Domain::widest(eval_lhs, eval_rhs);
+        if op == BinaryOperator::Divide && self.division == Division::Exact {
+            domain = domain.max(Domain::Rational);
+        }
+        match domain {
+            // Floats follow IEEE 754, so dividing by zero gives an infinity or NaN
+            Domain::Float => {
+                let a = expect_float(operator, eval_lhs, lhs)?;
+                let b = expect_float(operator, eval_rhs, rhs)?;
+                Ok(Value::Float(match op {
+                    BinaryOperator::Add => a + b,
+                    BinaryOperator::Subtract => a - b,
+                    BinaryOperator::Multiply => a * b,
+                    BinaryOperator::Divide => a / b,
+                    BinaryOperator::Remainder => a % b,
+                    _ => a.powf(b),
+                }))
+            }
+            Domain::Decimal => {
+                let a = expect_decimal(operator, eval_lhs, lhs)?;
+                let b = expect_decimal(operator, eval_rhs, rhs)?;
+                if matches!(op, BinaryOperator::Divide | BinaryOperator::Remainder) && b.is_zero() {
+                    return Err(division_by_zero());
+                }
+                let result = match op {
+                    BinaryOperator::Add => a.checked_add(b),
+                    BinaryOperator::Subtract => a.checked_sub(b),
+                    BinaryOperator::Multiply => a.checked_mul(b),
+                    BinaryOperator::Divide => a.checked_div(b),
+                    BinaryOperator::Remainder => a.checked_rem(b),
+                    _ => return decimal_power(a, b, node).map(Value::Decimal),
+                };
+                result.map(Value::Decimal).ok_or_else(overflow)
+            }
+            Domain::Rational
+                if !matches!(op, BinaryOperator::Remainder | BinaryOperator::Power) =>
+            {
+                let a = expect_rational(operator, eval_lhs, lhs)?;
+                let b = expect_rational(operator, eval_rhs, rhs)?;
+                if op == BinaryOperator::Divide && *b.numer() == int(0) {
+                    return Err(division_by_zero());
+                }
+                a.apply(op, &b).map(Value::from).ok_or_else(overflow)
+            }
+            _ => {
+                let a = expect_integer(operator, eval_lhs, lhs)?;
+                let b = expect_integer(operator, eval_rhs, rhs)?;
+                match op {
+                    BinaryOperator::Remainder => remainder(&a, &b, node, integer::rem),
+                    BinaryOperator::Power => self.power(operator, &a, &b, node),
+                    _ if op == BinaryOperator::Divide && b == int(0) => Err(division_by_zero()),
+                    _ => self
+                        .arithmetic
+                        .apply(op, &a, &b)
+                        .map(Value::Int)
+                        .ok_or_else(overflow),
+                }
+            }
+        }
+    }
+
+    // Orders the operands of `op` as the widest kind of number among them, or
+    // as strings if either is a string. Floats are only partially ordered, so
+    // the order is None if either is NaN.
+    fn compare(
+        &self,
+        op: Operator,
+        (lhs, eval_lhs): (&Node, &Value),
+        (rhs, eval_rhs): (&Node, &Value),
+        node: &Node,
+    ) -> Result<Option<Ordering>, EvalError> {
+        if matches!(eval_lhs, Value::String(_)) || matches!(eval_rhs, Value::String(_)) {
+            let a = expect_string(op, eval_lhs, lhs)?;
+            let b = expect_string(op, eval_rhs, rhs)?;
+            return Ok(Some(a.cmp(&b)));
+        }
+        match Domain::widest(eval_lhs, eval_rhs) {
+            Domain::Integer => {
+                let a = expect_integer(op, eval_lhs, lhs)?;
+                let b = expect_integer(op, eval_rhs, rhs)?;
+                Ok(Some(a.cmp(&b)))
+            }
+            Domain::Rational => {
+                let a = expect_rational(op, eval_lhs, lhs)?;
+                let b = expect_rational(op, eval_rhs, rhs)?;
+                match a.compare(&b) {
+                    Some(ordering) => Ok(Some(ordering)),
+                    None => Err(EvalError::Overflow {
+                        op,
+                        expr: node.to_expression(),
+                        span: node.span,
+                    }),
+                }
+            }
+            Domain::Decimal => {
+                let a = expect_decimal(op, eval_lhs, lhs)?;
+                let b = expect_decimal(op, eval_rhs, rhs)?;
+                Ok(Some(a.cmp(&b)))
+            }
+            Domain::Float => {
+                let a = expect_float(op, eval_lhs, lhs)?;
+                let b = expect_float(op, eval_rhs, rhs)?;
+                // Integers and fractions may not convert to floats exactly, so a
+                // whole float is compared with them as the integer it equals
+                let whole = |float: f64, other: &Value| {
+                    float.fract() == 0.0 && matches!(other, Value::Int(_) | Value::Rational(_))
+                };
+                if matches!(eval_lhs, Value::Float(_)) && whole(a, eval_rhs) {
+                    return match integer::from_f64(a) {
+                        Some(a) => self.compare(op, (lhs, &Value::Int(a)), (rhs, eval_rhs), node),
+                        // A float too large for an integer is beyond every integer and fraction
+                        None => Ok(Some(if a > 0.0 {
+                            Ordering::Greater
+                        } else {
+                            Ordering::Less
+                        })),
+                    };
+                }
+                if matches!(eval_rhs, Value::Float(_)) && whole(b, eval_lhs) {
+                    return match integer::from_f64(b) {
+                        Some(b) => self.compare(op, (lhs, eval_lhs), (rhs, &Value::Int(b)), node),
+                        None => Ok(Some(if b > 0.0 {
+                            Ordering::Less
+                        } else {
+                            Ordering::Greater
+                        })),
+                    };
+                }
+                Ok(a.partial_cmp(&b))
+            }
+        }
+    }
+
+    // Applies `builtin` to `values`, the values of its arguments `args` in the call `node`
+    fn apply_builtin(
+        &self,
+        builtin: Builtin,
+        args: &[Rc<Node>],
+        values: Vec<Value>,
+        node: &Node,
+    ) -> Result<Value, EvalError> {
+        let operator = Operator::Builtin(builtin);
+        // The builtins on strings take arguments of mixed types, unlike the rest
+        if matches!(
+            builtin,
+            Builtin::Len | Builtin::Substring | Builtin::ToString | Builtin::ParseInt
+        ) {
+            return apply_string_builtin(builtin, args, &values);
+        }
+        // Like the arithmetic operators, the builtins that only order their
+        // arguments work in the widest kind of number among them. The rest
+        // take integers only.
+        let domain = values
+            .iter()
+            .map(|value| Domain::of(value).unwrap_or(Domain::Integer))
+            .max()
+            .unwrap_or(Domain::Integer);
+        if domain > Domain::Integer
+            && matches!(
+                builtin,
+                Builtin::Abs | Builtin::Min | Builtin::Max | Builtin::Clamp
+            )
+        {
+            return apply_ordering_builtin(builtin, domain, args, &values, node);
+        }
+        let ints = values
+            .iter()
+            .zip(args)
+            .map(|(value, arg)| expect_integer(operator, value, arg))
+            .collect::<Result<Vec<Int>, EvalError>>()?;
+        let overflow = || EvalError::Overflow {
+            op: operator,
+            expr: node.to_expression(),
+            span: node.span,
+        };
+        match (builtin, ints.as_slice()) {
+            (Builtin::Abs, [a]) if *a < int(0) => self
+                .arithmetic
+                .negate(a)
+                .map(Value::Int)
+                .ok_or_else(overflow),
+            (Builtin::Abs, [a]) => Ok(Value::Int(integer::dup(a))),
+            (Builtin::Min, [a, b]) => Ok(Value::Int(integer::dup(a.min(b)))),
+            (Builtin::Max, [a, b]) => Ok(Value::Int(integer::dup(a.max(b)))),
+            (Builtin::Clamp, [a, lo, hi]) => Ok(Value::Int(integer::dup(a.min(hi).max(lo)))),
+            (Builtin::Gcd, [a, b]) => self
+                .arithmetic
+                .gcd(a, b)
+                .map(Value::Int)
+                .ok_or_else(overflow),
+            (Builtin::Mod, [a, b]) => remainder(a, b, node, integer::rem_euclid),
+            (Builtin::Pow, [a, b]) => self.power(operator, a, b, node),
+            _ => unreachable!("the parser checks the number of arguments"),
+        }
+    }
+
+    // Raises `a` to the power `b` for `op`, which is '^' or `pow`
+    fn power(&self, op: Operator, a: &Int, b: &Int, node: &Node) -> Result<Value, EvalError> {
+        if *b < int(0) {
+            return Err(EvalError::NegativeExponent {
+                expr: node.to_expression(),
+                span: node.span,
+            });
+        }
+        match integer::to_u64(b).and_then(|exp| self.arithmetic.pow(a, exp)) {
+            Some(result) => Ok(Value::Int(result)),
+            None => Err(EvalError::Overflow {
+                op,
+                expr: node.to_expression(),
+                span: node.span,
+            }),
+        }
+    }
+}
+
+// Helper function to apply `abs`, `min`, `max` or `clamp` to `values`, the
+// values of its arguments `args` in the call `node`, as numbers of `domain`
+fn apply_ordering_builtin(
+    builtin: Builtin,
+    domain: Domain,
+    args: &[Rc<Node>],
+    values: &[Value],
+    node: &Node,
+) -> Result<Value, EvalError> {
+    let operator = Operator::Builtin(builtin);
+    let overflow = || EvalError::Overflow {
+        op: operator,
+        expr: node.to_expression(),
+        span: node.span,
+    };
+    match domain {
+        Domain::Float => {
+            let floats = values
+                .iter()
+                .zip(args)
+                .map(|(value, arg)| expect_float(operator, value, arg))
+                .collect::<Result<Vec<f64>, EvalError>>()?;
+            // NaN is unordered, so it is the result rather than being skipped
+            // as f64::min and f64::max would
+            if floats.iter().any(|a| a.is_nan()) {
+                return Ok(Value::Float(f64::NAN));
+            }
+            Ok(Value::Float(match (builtin, floats.as_slice()) {
+                (Builtin::Abs, [a]) => a.abs(),
+                (Builtin::Min, [a, b]) => a.min(*b),
+                (Builtin::Max, [a, b]) => a.max(*b),
+                (Builtin::Clamp, [a, lo, hi]) => a.min(*hi).max(*lo),
+                _ => unreachable!("the parser checks the number of arguments"),
+            }))
+        }
+        Domain::Decimal => {
+            let decimals = values
+                .iter()
+                .zip(args)
+                .map(|(value, arg)| expect_decimal(operator, value, arg))
+                .collect::<Result<Vec<Decimal>, EvalError>>()?;
+            Ok(Value::Decimal(match (builtin, decimals.as_slice()) {
+                (Builtin::Abs, [a]) => a.abs(),
+                (Builtin::Min, [a, b]) => *a.min(b),
+                (Builtin::Max, [a, b]) => *a.max(b),
+                (Builtin::Clamp, [a, lo, hi]) => *a.min(hi).max(lo),
+                _ => unreachable!("the parser checks the number of arguments"),
+            }))
+        }
+        _ => {
+            let rationals = values
+                .iter()
+                .zip(args)
+                .map(|(value, arg)| expect_rational(operator, value, arg))
+                .collect::<Result<Vec<Rational>, EvalError>>()?;
+            let less = |a: &Rational, b: &Rational| match a.compare(b) {
+                Some(ordering) => Ok(ordering.is_lt()),
+                None => Err(overflow()),
+            };
+            let result = match (builtin, rationals.as_slice()) {
+                (Builtin::Abs, [a]) if *a.numer() < int(0) => a.negate().ok_or_else(overflow)?,
+                (Builtin::Abs, [a]) => a.clone(),
+                (Builtin::Min, [a, b]) => if less(b, a)? { b } else { a }.clone(),
+                (Builtin::Max, [a, b]) => if less(a, b)? { b } else { a }.clone(),
+                (Builtin::Clamp, [a, lo, hi]) => {
+                    let a = if less(hi, a)? { hi } else { a };
+                    if less(a, lo)? { lo } else { a }.clone()
+                }
+                _ => unreachable!("the parser checks the number of arguments"),
+            };
+            Ok(Value::from(result))
+        }
+    }
+}
+
+// Helper function to raise the decimal `a` to the power `b` for the '^' in `node`.
+// A result too close to zero to hold is rounded to zero, like one that has
+// more digits than a decimal holds.
+fn decimal_power(a: Decimal, b: Decimal, node: &Node) -> Result<Decimal, EvalError> {
+    if a.is_zero() && b.is_sign_negative() && !b.is_zero() {
+        return Err(EvalError::DivisionByZero {
+            expr: node.to_expression(),
+            span: node.span,
+        });
+    }
+    if a.is_sign_negative() && !b.fract().is_zero() {
+        return Err(EvalError::NegativeBase {
+            expr: node.to_expression(),
+            span: node.span,
+        });
+    }
+    if let Some(result) = a.checked_powd(b) {
+        return Ok(result);
+    }
+    let shrinks = if b.is_sign_positive() {
+        a.abs() < Decimal::ONE
+    } else {
+        a.abs() > Decimal::ONE
+    };
+    if shrinks {
+        return Ok(Decimal::ZERO);
+    }
+    Err(EvalError::Overflow {
+        op: Operator::Binary(BinaryOperator::Power),
+        expr: node.to_expression(),
+        span: node.span,
+    })
+}
+
+// Helper function to apply the builtin on strings `builtin` to `values`, the values of its arguments `args`
+fn apply_string_builtin(
+    builtin: Builtin,
+    args: &[Rc<Node>],
+    values: &[Value],
+) -> Result<Value, EvalError> {
+    let operator = Operator::Builtin(builtin);
+    match (builtin, values, args) {
+        (Builtin::ToString, [a], _) => Ok(Value::String(a.to_string())),
+        (Builtin::Len, [s], [s_node]) => {
+            let s = expect_string(operator, s, s_node)?;
+            Ok(Value::Int(int(s.chars().count() as i64)))
+        }
+        (Builtin::Substring, [s, start, end], [s_node, start_node, end_node]) => {
+            let s = expect_string(operator, s, s_node)?;
+            let len = s.chars().count();
+            let start = char_index(&expect_integer(operator, start, start_node)?, len);
+            let end = char_index(&expect_integer(operator, end, end_node)?, len);
+            let count = end.saturating_sub(start);
+            Ok(Value::String(s.chars().skip(start).take(count).collect()))
+        }
+        (Builtin::ParseInt, [s], [s_node]) => {
+            let s = expect_string(operator, s, s_node)?;
+            match s.trim().parse::<Int>() {
+                Ok(value) => Ok(Value::Int(value)),
+                Err(_) => Err(EvalError::InvalidInteger {
+                    text: s,
+                    expr: s_node.to_expression(),
+                    span: s_node.span,
+                }),
+            }
+        }
+        _ => unreachable!("the parser checks the number of arguments"),
+    }
+}
+
+// Helper function to take the remainder of `a` divided by `b` for `node` with `rem`
+fn remainder(
+    a: &Int,
+    b: &Int,
+    node: &Node,
+    rem: fn(&Int, &Int) -> Int,
+) -> Result<Value, EvalError> {
+    if *b == int(0) {
+        return Err(EvalError::DivisionByZero {
+            expr: node.to_expression(),
+            span: node.span,
+        });
+    }
+    Ok(Value::Int(rem(a, b)))
+}
+
+// The kinds of number, from narrowest to widest. Operations on numbers of
+// different kinds convert them to the wider kind.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+enum Domain {
+    Integer,
+    Rational,
+    Decimal,
+    Float,
+}
+
+impl Domain {
+    // The kind of number `value` is, or None if it is not a number
+    fn of(value: &Value) -> Option<Domain> {
+        match value {
+            Value::Int(_) => Some(Domain::Integer),
+            Value::Rational(_) => Some(Domain::Rational),
+            Value::Decimal(_) => Some(Domain::Decimal),
+            Value::Float(_) => Some(Domain::Float),
+            _ => None,
+        }
+    }
+
+    // The wider kind of `lhs` and `rhs`. Values that are not numbers count as
+    // integers, so operations report them as type mismatches.
+    fn widest(lhs: &Value, rhs: &Value) -> Domain {
+        let of = |value| Domain::of(value).unwrap_or(Domain::Integer);
+        of(lhs).max(of(rhs))
+    }
+}
+
+// Helper function to build the error for the curried call `node`, whose function
+// position evaluated to a non-function after taking the arguments inside it
+fn too_many_arguments(node: &Rc<Node>, frames: &[Frame]) -> EvalError {
+    // Count the arguments supplied up to and including this call, every one
+    // of which but the last was taken
+    let mut found = 0;
+    let mut inner = node;
+    while let NodeKind::Apply { func_expr, .. } = &inner.kind {
+        inner = func_expr;
+        found += 1;
+    }
+    let expected = found - 1;
+
+    // Applications still waiting for `node` as their function supply the rest
+    let mut call = node.clone();
+    for frame in frames.iter().rev() {
+        let Frame::ApplyFunc { node: outer, .. } = frame else {
+            break;
+        };
+        match &outer.kind {
+            NodeKind::Apply { func_expr, .. } if Rc::ptr_eq(func_expr, &call) => {
+                found += 1;
+                call = outer.clone();
+            }
+            _ => break,
+        }
+    }
+
+    EvalError::TooManyArguments {
+        expected,
+        found,
+        expr: call.to_expression(),
+        span: call.span,
+    }
+}
+
+// Helper function to unwrap the integer operand `node` of `op`, which evaluated to `value`
+fn expect_integer(op: Operator, value: &Value, node: &Node) -> Result<Int, EvalError> {
+    match value {
+        Value::Int(a) => Ok(integer::dup(a)),
+        _ => Err(EvalError::TypeMismatch {
+            op,
+            expected: Type::Integer,
+            found: value.type_of(),
+            expr: node.to_expression(),
+            span: node.span,
+        }),
+    }
+}
+
+// Helper function to unwrap the numeric operand `node` of `op`, which
+// evaluated to `value`, as a fraction
+fn expect_rational(op: Operator, value: &Value, node: &Node) -> Result<Rational, EvalError> {
+    match value {
+        Value::Rational(a) => Ok(a.clone()),
+        _ => expect_integer(op, value, node).map(Rational::from),
+    }
+}
+
+// Helper function to unwrap the numeric operand `node` of `op`, which
+// evaluated to `value`, as a decimal
+fn expect_decimal(op: Operator, value: &Value, node: &Node) -> Result<Decimal, EvalError> {
+    let decimal = match value {
+        Value::Decimal(a) => Some(*a),
+        Value::Int(a) => integer::to_decimal(a),
+        Value::Rational(a) => integer::to_decimal(a.numer())
+            .zip(integer::to_decimal(a.denom()))
+            .and_then(|(numer, denom)| numer.checked_div(denom)),
+        _ => {
+            return Err(EvalError::TypeMismatch {
+                op,
+                expected: Type::Decimal,
+                found: value.type_of(),
+                expr: node.to_expression(),
+                span: node.span,
+            })
+        }
+    };
+    decimal.ok_or_else(|| EvalError::DecimalOutOfRange {
+        expr: node.to_expression(),
+        span: node.span,
+    })
+}
+
+// Helper function to unwrap the numeric operand `node` of `op`, which
+// evaluated to `value`, as the nearest float
+fn expect_float(op: Operator, value: &Value, node: &Node) -> Result<f64, EvalError> {
+    match value.to_f64() {
+        Some(a) => Ok(a),
+        None => Err(EvalError::TypeMismatch {
+            op,
+            expected: Type::Float,
+            found: value.type_of(),
+            expr: node.to_expression(),
+            span: node.span,
+        }),
+    }
+}
+
+// Helper function to read the float literal `text` as a decimal. It is read
+// exactly, so "0.1" is one tenth rather than the binary float nearest to it,
+// and a literal with more digits or a larger magnitude than a decimal can
+// hold gives None rather than being rounded.
+fn decimal_literal(text: &str) -> Option<Decimal> {
+    let (mantissa, exponent) = match text.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i64>().ok()?),
+        None => (text, 0),
+    };
+    // Trailing zeros after the point are only kept if there is room for them
+    let mut value = Decimal::from_str_exact(mantissa)
+        .or_else(|_| Decimal::from_str_exact(mantissa.trim_end_matches('0').trim_end_matches('.')))
+        .ok()?;
+    if value.is_zero() {
+        return Some(value);
+    }
+    if exponent < 0 {
+        let scale = u32::try_from(i64::from(value.scale()) - exponent).ok()?;
+        value.set_scale(scale).ok()?;
+    } else {
+        // Each step multiplies by ten, so this overflows within 29 steps
+        for _ in 0..exponent {
+            value = value.checked_mul(Decimal::TEN)?;
+        }
+    }
+    Some(value)
+}
+
+// Helper function to unwrap the string operand `node` of `op`, which evaluated to `value`
+fn expect_string(op: Operator, value: &Value, node: &Node) -> Result<String, EvalError> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        _ => Err(EvalError::TypeMismatch {
+            op,
+            expected: Type::String,
+            found: value.type_of(),
+            expr: node.to_expression(),
+            span: node.span,
+        }),
+    }
+}
+
+// Helper function to clamp the character index `index` to a string of `len` characters
+fn char_index(index: &Int, len: usize) -> usize {
+    match integer::to_u64(index) {
+        Some(index) => usize::try_from(index).map_or(len, |index| index.min(len)),
+        None if *index < int(0) => 0,
+        None => len,
+    }
+}
+
+// Helper function to unwrap the boolean operand `node` of `op`, which evaluated to `value`
+fn expect_boolean(op: Operator, value: &Value, node: &Node) -> Result<bool, EvalError> {
+    match value {
+        Value::Bool(b) => Ok(*b),
+        _ => Err(EvalError::TypeMismatch {
+            op,
+            expected: Type::Boolean,
+            found: value.type_of(),
+            expr: node.to_expression(),
+            span: node.span,
+        }),
+    }
+}
@@ -1,8 +1,23 @@
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Error};
 
+use crate::error::EvalError;
+use crate::evaluator::{Arithmetic, Division, Evaluator, Floats};
+use crate::integer::{int, Int};
+use crate::parser::{infix_binding_power, write_quoted, PREFIX_BINDING_POWER};
+use crate::value::{Bindings, Value};
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Expression {
-    Integer(i64),
+    Integer(Int),
+    /// A float literal and the text it was written as, which
+    /// [`Floats::Decimal`] reads exactly rather than through the `f64`.
+    Float {
+        value: f64,
+        text: String,
+    },
+    /// A string literal, such as `"total: "`.
+    String(String),
     Variable(String),
     Boolean(bool),
     BinaryOp {
@@ -27,16 +42,43 @@ pub enum Expression {
         func_expr: Box<Expression>,
         arg_expr: Box<Expression>,
     },
+    Let {
+        name: String,
+        value: Box<Expression>,
+        body: Box<Expression>,
+    },
+    /// `let rec name = func param => func_body in body`, where `name` is bound
+    /// to the function both in its own body and in `body`.
+    LetRec {
+        name: String,
+        param: String,
+        func_body: Box<Expression>,
+        body: Box<Expression>,
+    },
+    /// A call of a built-in function, such as `max(a, b)`.
+    Call {
+        builtin: Builtin,
+        args: Vec<Expression>,
+    },
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum BinaryOperator {
+    /// Addition, or concatenation if either operand is a string.
     Add,
     Subtract,
     Multiply,
     Divide,
+    /// The remainder of truncating division, with the sign of the dividend.
+    Remainder,
+    /// Exponentiation, which is right associative.
+    Power,
     LessThan,
+    GreaterThan,
+    LessEqual,
+    GreaterEqual,
     Equals,
+    NotEquals,
     And,
     Or,
 }
@@ -44,26 +86,363 @@ pub enum BinaryOperator {
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum UnaryOperator {
     Not,
+    Negate,
+}
+
+/// A function that is built into the language and called as `name(args)`.
+///
+/// `abs`, `min`, `max` and `clamp` take any numbers and work in the widest
+/// kind among their arguments, as the arithmetic operators do. `gcd`, `mod`
+/// and `pow` take integers only.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Builtin {
+    /// `abs(a)`, the absolute value.
+    Abs,
+    /// `min(a, b)`, the smaller argument.
+    Min,
+    /// `max(a, b)`, the larger argument.
+    Max,
+    /// `gcd(a, b)`, the non-negative greatest common divisor.
+    Gcd,
+    /// `clamp(a, lo, hi)`, `a` limited to between `lo` and `hi`, or `lo` if `lo > hi`.
+    Clamp,
+    /// `mod(a, b)`, the remainder of Euclidean division, which is never negative.
+    Mod,
+    /// `pow(a, b)`, the same as `a ^ b`.
+    Pow,
+    /// `len(s)`, the number of characters in the string `s`.
+    Len,
+    /// `substring(s, start, end)`, the characters of `s` from index `start` up
+    /// to but not including `end`. Indices count characters from zero and are
+    /// clamped to the string, so the result is empty if `start >= end`.
+    Substring,
+    /// `to_string(a)`, the value `a` written as a string, as it is displayed.
+    ToString,
+    /// `parse_int(s)`, the integer written in the string `s`, which may be
+    /// surrounded by whitespace.
+    ParseInt,
+}
+
+impl Builtin {
+    /// Looks up the built-in function called `name`.
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        match name {
+            "abs" => Some(Builtin::Abs),
+            "min" => Some(Builtin::Min),
+            "max" => Some(Builtin::Max),
+            "gcd" => Some(Builtin::Gcd),
+            "clamp" => Some(Builtin::Clamp),
+            "mod" => Some(Builtin::Mod),
+            "pow" => Some(Builtin::Pow),
+            "len" => Some(Builtin::Len),
+            "substring" => Some(Builtin::Substring),
+            "to_string" => Some(Builtin::ToString),
+            "parse_int" => Some(Builtin::ParseInt),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Abs => "abs",
+            Builtin::Min => "min",
+            Builtin::Max => "max",
+            Builtin::Gcd => "gcd",
+            Builtin::Clamp => "clamp",
+            Builtin::Mod => "mod",
+            Builtin::Pow => "pow",
+            Builtin::Len => "len",
+            Builtin::Substring => "substring",
+            Builtin::ToString => "to_string",
+            Builtin::ParseInt => "parse_int",
+        }
+    }
+
+    /// The number of arguments the function takes.
+    pub fn arity(&self) -> usize {
+        match self {
+            Builtin::Abs | Builtin::Len | Builtin::ToString | Builtin::ParseInt => 1,
+            Builtin::Clamp | Builtin::Substring => 3,
+            _ => 2,
+        }
+    }
+}
+
+/// Any kind of operator, for reporting which one an error came from.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Operator {
+    Binary(BinaryOperator),
+    Unary(UnaryOperator),
+    Builtin(Builtin),
 }
 
+/// Prints the expression in infix syntax, with parentheses only where
+/// precedence requires them. The output parses back to an equal expression
+/// with [`Syntax::Infix`](crate::parser::Syntax::Infix).
 impl Display for Expression {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
-        match self {
+        fmt_infix(self, f, 0, true)
+    }
+}
+
+// Writes `expr` in infix syntax where the surrounding operator binds with
+// `min_power`, and `at_end` says whether nothing follows it before the next
+// closing token. `if`, `func` and `let` (including `let rec`) extend as far right as they can, so they are
+// parenthesised unless they are at the end.
+fn fmt_infix(
+    expr: &Expression,
+    f: &mut std::fmt::Formatter<'_>,
+    min_power: u8,
+    at_end: bool,
+) -> Result<(), Error> {
+    match expr {
+        Expression::Integer(value) if *value < int(0) && min_power > PREFIX_BINDING_POWER => {
+            // "-2 ^ 2" reads as the negation of "2 ^ 2"
+            write!(f, "({})", value)
+        }
+        Expression::Float { value, .. }
+            if value.is_sign_negative() && min_power > PREFIX_BINDING_POWER =>
+        {
+            write!(f, "({})", expr.canonical())
+        }
+        Expression::Integer(_)
+        | Expression::Float { .. }
+        | Expression::String(_)
+        | Expression::Variable(_)
+        | Expression::Boolean(_) => {
+            write!(f, "{}", expr.canonical())
+        }
+        Expression::BinaryOp { op, lhs, rhs } => {
+            let (left_power, right_power) = infix_binding_power(*op);
+            if left_power < min_power {
+                write!(f, "(")?;
+                fmt_infix(expr, f, 0, true)?;
+                return write!(f, ")");
+            }
+            // The left operand of a right associative operator must bind tighter than it
+            let lhs_power = if left_power > right_power {
+                left_power + 1
+            } else {
+                left_power
+            };
+            fmt_infix(lhs, f, lhs_power, false)?;
+            write!(f, " {} ", op)?;
+            fmt_infix(rhs, f, right_power, at_end)
+        }
+        Expression::UnaryOp { .. } if min_power > PREFIX_BINDING_POWER => {
+            write!(f, "(")?;
+            fmt_infix(expr, f, 0, true)?;
+            write!(f, ")")
+        }
+        Expression::UnaryOp {
+            op: UnaryOperator::Negate,
+            child,
+        } if matches!(&**child, Expression::Integer(value) if *value >= int(0))
+            || matches!(**child, Expression::Float { value, .. } if value.is_sign_positive()) =>
+        {
+            // "-5" would read back as a negative literal rather than a negation
+            write!(f, "-({})", child)
+        }
+        Expression::UnaryOp { op, child } => {
+            write!(f, "{}", op)?;
+            fmt_infix(child, f, PREFIX_BINDING_POWER, at_end)
+        }
+        Expression::Func { .. }
+        | Expression::If { .. }
+        | Expression::Let { .. }
+        | Expression::LetRec { .. }
+            if !at_end =>
+        {
+            write!(f, "(")?;
+            fmt_infix(expr, f, 0, true)?;
+            write!(f, ")")
+        }
+        Expression::Func { param, body } => {
+            let (params, body) = curried_params(param, body);
+            write!(f, "func {} => ", params.join(", "))?;
+            fmt_infix(body, f, 0, true)
+        }
+        Expression::If {
+            condition,
+            then_expr,
+            else_expr,
+        } => {
+            write!(f, "if ")?;
+            fmt_infix(condition, f, 0, true)?;
+            write!(f, " then ")?;
+            fmt_infix(then_expr, f, 0, true)?;
+            write!(f, " else ")?;
+            fmt_infix(else_expr, f, 0, true)
+        }
+        Expression::Apply {
+            func_expr,
+            arg_expr,
+        } => {
+            let (func_expr, args) = curried_args(func_expr, arg_expr);
+            write!(f, "apply(")?;
+            fmt_infix(func_expr, f, 0, true)?;
+            for arg in args {
+                write!(f, ", ")?;
+                fmt_infix(arg, f, 0, true)?;
+            }
+            write!(f, ")")
+        }
+        Expression::Let { name, value, body } => {
+            write!(f, "let {} = ", name)?;
+            fmt_infix(value, f, 0, true)?;
+            write!(f, " in ")?;
+            fmt_infix(body, f, 0, true)
+        }
+        Expression::LetRec {
+            name,
+            param,
+            func_body,
+            body,
+        } => {
+            let (params, func_body) = curried_params(param, func_body);
+            write!(f, "let rec {} = func {} => ", name, params.join(", "))?;
+            fmt_infix(func_body, f, 0, true)?;
+            write!(f, " in ")?;
+            fmt_infix(body, f, 0, true)
+        }
+        Expression::Call { builtin, args } => {
+            write!(f, "{}(", builtin)?;
+            for (index, arg) in args.iter().enumerate() {
+                if index > 0 {
+                    write!(f, ", ")?;
+                }
+                fmt_infix(arg, f, 0, true)?;
+            }
+            write!(f, ")")
+        }
+    }
+}
+
+// Collects `param` and the parameters of any functions directly in `body`, which
+// print as a single "func a, b, c =>", and returns them with the innermost body
+fn curried_params<'a>(param: &'a str, body: &'a Expression) -> (Vec<&'a str>, &'a Expression) {
+    let mut params = vec![param];
+    let mut body = body;
+    while let Expression::Func { param, body: inner } = body {
+        params.push(param);
+        body = inner;
+    }
+    (params, body)
+}
+
+// Flattens "apply(apply(f, a), b)" into `f` and its arguments `[a, b]`, which
+// print as the single "apply(f, a, b)"
+fn curried_args<'a>(
+    func_expr: &'a Expression,
+    arg_expr: &'a Expression,
+) -> (&'a Expression, Vec<&'a Expression>) {
+    let mut args = vec![arg_expr];
+    let mut func_expr = func_expr;
+    while let Expression::Apply {
+        func_expr: inner,
+        arg_expr,
+    } = func_expr
+    {
+        args.push(arg_expr);
+        func_expr = inner;
+    }
+    args.reverse();
+    (func_expr, args)
+}
+
+/// Displays an expression in the default prefix syntax, which parses back to
+/// an equal expression with [`Parser::new`](crate::parser::Parser::new).
+pub struct Canonical<'a>(&'a Expression);
+
+impl Display for Canonical<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match self.0 {
             Expression::Integer(value) => write!(f, "{}", value),
+            Expression::Float { text, .. } => write!(f, "{}", text),
+            Expression::String(text) => write_quoted(f, text),
             Expression::Variable(name) => write!(f, "{}", name),
             Expression::Boolean(value) => write!(f, "{}", if *value { "T" } else { "F" }),
-            Expression::BinaryOp { op, lhs, rhs } => write!(f, "{} {} {}", lhs, op, rhs),
-            Expression::UnaryOp { op, child } => write!(f, "{}{}", op, child),
-            Expression::Func { param, body } => write!(f, "func {} => {}", param, body),
+            Expression::BinaryOp { op, lhs, rhs } => {
+                write!(f, "{}({}, {})", op, lhs.canonical(), rhs.canonical())
+            }
+            Expression::UnaryOp {
+                op: UnaryOperator::Negate,
+                child,
+            } => write!(f, "-({})", child.canonical()),
+            Expression::UnaryOp {
+                op: UnaryOperator::Not,
+                child,
+            } if matches!(
+                **child,
+                Expression::BinaryOp {
+                    op: BinaryOperator::Equals,
+                    ..
+                }
+            ) =>
+            {
+                // "!=(a, b)" would read back as "!=" rather than "!" and "="
+                write!(f, "! {}", child.canonical())
+            }
+            Expression::UnaryOp { op, child } => write!(f, "{}{}", op, child.canonical()),
+            Expression::Func { param, body } => {
+                let (params, body) = curried_params(param, body);
+                write!(f, "func {} => {}", params.join(", "), body.canonical())
+            }
             Expression::If {
                 condition,
                 then_expr,
                 else_expr,
-            } => write!(f, "if {} then {} else {}", condition, then_expr, else_expr),
+            } => write!(
+                f,
+                "if {} then {} else {}",
+                condition.canonical(),
+                then_expr.canonical(),
+                else_expr.canonical()
+            ),
             Expression::Apply {
                 func_expr,
                 arg_expr,
-            } => write!(f, "{} ({})", func_expr, arg_expr),
+            } => {
+                let (func_expr, args) = curried_args(func_expr, arg_expr);
+                write!(f, "apply({}", func_expr.canonical())?;
+                for arg in args {
+                    write!(f, ", {}", arg.canonical())?;
+                }
+                write!(f, ")")
+            }
+            Expression::Let { name, value, body } => write!(
+                f,
+                "let {} = {} in {}",
+                name,
+                value.canonical(),
+                body.canonical()
+            ),
+            Expression::LetRec {
+                name,
+                param,
+                func_body,
+                body,
+            } => {
+                let (params, func_body) = curried_params(param, func_body);
+                write!(
+                    f,
+                    "let rec {} = func {} => {} in {}",
+                    name,
+                    params.join(", "),
+                    func_body.canonical(),
+                    body.canonical()
+                )
+            }
+            Expression::Call { builtin, args } => {
+                write!(f, "{}(", builtin)?;
+                for (index, arg) in args.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg.canonical())?;
+                }
+                write!(f, ")")
+            }
         }
     }
 }
@@ -75,201 +454,347 @@ impl Display for BinaryOperator {
             BinaryOperator::Subtract => write!(f, "-"),
             BinaryOperator::Multiply => write!(f, "*"),
             BinaryOperator::Divide => write!(f, "/"),
+            BinaryOperator::Remainder => write!(f, "%"),
+            BinaryOperator::Power => write!(f, "^"),
             BinaryOperator::LessThan => write!(f, "<"),
+            BinaryOperator::GreaterThan => write!(f, ">"),
+            BinaryOperator::LessEqual => write!(f, "<="),
+            BinaryOperator::GreaterEqual => write!(f, ">="),
             BinaryOperator::Equals => write!(f, "="),
+            BinaryOperator::NotEquals => write!(f, "!="),
             BinaryOperator::And => write!(f, "&"),
             BinaryOperator::Or => write!(f, "|"),
         }
     }
 }
 
+impl Display for Builtin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{}", self.name())
+    }
+}
+
 impl Display for UnaryOperator {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
         match self {
             UnaryOperator::Not => write!(f, "!"),
+            UnaryOperator::Negate => write!(f, "-"),
+        }
+    }
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Operator::Binary(op) => write!(f, "{}", op),
+            Operator::Unary(op) => write!(f, "{}", op),
+            Operator::Builtin(builtin) => write!(f, "{}", builtin),
         }
     }
 }
 
 impl Expression {
-    pub fn eval(&self) -> Result<Expression, String> {
+    /// An expression for the float `value`, written so that it reads back as
+    /// the same float. That is a float literal, except that there are none for
+    /// infinities and NaN, which are `/(1.0, 0.0)`, `/(-1.0, 0.0)` and
+    /// `/(0.0, 0.0)` instead. Under [`Floats::Decimal`] those divide by zero.
+    pub fn float(value: f64) -> Expression {
+        if !value.is_finite() {
+            let numer = if value.is_nan() { 0.0 } else { value.signum() };
+            return Expression::BinaryOp {
+                op: BinaryOperator::Divide,
+                lhs: Box::new(Expression::float(numer)),
+                rhs: Box::new(Expression::float(0.0)),
+            };
+        }
+        // Debug keeps the point in whole floats, so "2.0" does not read back as an integer
+        Expression::Float {
+            value,
+            text: format!("{:?}", value),
+        }
+    }
+
+    /// Returns a [`Display`] adapter that prints the expression in prefix syntax.
+    pub fn canonical(&self) -> Canonical<'_> {
+        Canonical(self)
+    }
+
+    /// The immediate subexpressions, left to right as they appear in the source.
+    pub fn children(&self) -> Vec<&Expression> {
         match self {
-            Expression::Integer(_) => {
-                // Integers just evaluate to themselves
-                Ok(self.clone())
-            }
-            Expression::Variable(_) => {
-                // Variables are not evaluated
-                Ok(self.clone())
-            }
-            Expression::Boolean(_) => {
-                // Booleans just evaluate to themselves
-                Ok(self.clone())
-            }
-            Expression::UnaryOp { op, child } => {
-                // Evaluate the child expression
-                let eval_child = child.eval()?;
-
-                // Apply the unary operator
-                match op {
-                    UnaryOperator::Not => match eval_child {
-                        Expression::Boolean(b) => Ok(Expression::Boolean(!b)),
-                        _ => Err("Invalid operand for 'Not' operator".to_string()),
-                    },
-                }
-            }
-            Expression::BinaryOp { op, lhs, rhs } => {
-                // Evaluate the left and right child expressions
-                let eval_lhs = lhs.eval()?;
-                let eval_rhs = rhs.eval()?;
-
-                // Apply the binary operator
-                match op {
-                    BinaryOperator::Add => {
-                        if let (Expression::Integer(a), Expression::Integer(b)) =
-                            (eval_lhs, eval_rhs)
-                        {
-                            Ok(Expression::Integer(a + b))
-                        } else {
-                            Err("Invalid operands for 'Add' operator".to_string())
-                        }
-                    }
-                    BinaryOperator::Subtract => {
-                        if let (Expression::Integer(a), Expression::Integer(b)) =
-                            (eval_lhs, eval_rhs)
-                        {
-                            Ok(Expression::Integer(a - b))
-                        } else {
-                            Err("Invalid operands for 'Subtract' operator".to_string())
-                        }
-                    }
-                    BinaryOperator::Multiply => {
-                        if let (Expression::Integer(a), Expression::Integer(b)) =
-                            (eval_lhs, eval_rhs)
-                        {
-                            Ok(Expression::Integer(a * b))
-                        } else {
-                            Err("Invalid operands for 'Multiply' operator".to_string())
-                        }
-                    }
-                    BinaryOperator::Divide => {
-                        if let (Expression::Integer(a), Expression::Integer(b)) =
-                            (eval_lhs, eval_rhs)
-                        {
-                            Ok(Expression::Integer(a / b))
-                        } else {
-                            Err("Invalid operands for 'Divide' operator".to_string())
-                        }
-                    }
-                    BinaryOperator::Equals => {
-                        if let (Expression::Integer(a), Expression::Integer(b)) =
-                            (eval_lhs, eval_rhs)
-                        {
-                            Ok(Expression::Boolean(a == b))
-                        } else {
-                            Err("Invalid operands for 'Equals' operator".to_string())
-                        }
-                    }
-                    BinaryOperator::LessThan => {
-                        if let (Expression::Integer(a), Expression::Integer(b)) =
-                            (eval_lhs, eval_rhs)
-                        {
-                            Ok(Expression::Boolean(a < b))
-                        } else {
-                            Err("Invalid operands for 'LessThan' operator".to_string())
-                        }
-                    }
-                    BinaryOperator::And => {
-                        if let (Expression::Boolean(a), Expression::Boolean(b)) =
-                            (eval_lhs, eval_rhs)
-                        {
-                            Ok(Expression::Boolean(a && b))
-                        } else {
-                            Err("Invalid operands for 'And' operator".to_string())
-                        }
-                    }
-                    BinaryOperator::Or => {
-                        if let (Expression::Boolean(a), Expression::Boolean(b)) =
-                            (eval_lhs, eval_rhs)
-                        {
-                            Ok(Expression::Boolean(a || b))
-                        } else {
-                            Err("Invalid operands for 'Or' operator".to_string())
-                        }
-                    }
-                }
-            }
-            Expression::Func { param: _, body: _ } => {
-                // Functions are not evaluated directly, they are kept as closures
-                // The closure captures the current environment and the parameter
-                Ok(self.clone())
-            }
-            Expression::Apply { func_expr, arg_expr } => {
-                // Evaluate the function expression and the argument expression
-                let eval_func = func_expr.eval()?;
-                let eval_arg = arg_expr.eval()?;
-
-                // Apply the function to the argument
-                match eval_func {
-                    Expression::Func { param, body } => {
-                        // Substitute the argument value into the function body
-                        let substituted_body = substitute(&body, &param, &eval_arg);
-
-                        // Evaluate the substituted body
-                        substituted_body.eval()
-                    }
-                    _ => Err("Invalid function expression in apply".to_string()),
-                }
-            }
+            Expression::Integer(_)
+            | Expression::Float { .. }
+            | Expression::String(_)
+            | Expression::Variable(_)
+            | Expression::Boolean(_) => vec![],
+            Expression::BinaryOp { lhs, rhs, .. } => vec![lhs, rhs],
+            Expression::UnaryOp { child, .. } => vec![child],
+            Expression::Func { body, .. } => vec![body],
             Expression::If {
                 condition,
                 then_expr,
                 else_expr,
-            } => {
-                let eval_condition = condition.eval()?;
-                match eval_condition {
-                    Expression::Boolean(cond) => {
-                        if cond {
-                            then_expr.eval()
-                        } else {
-                            else_expr.eval()
-                        }
-                    }
-                    _ => Err("Invalid condition for 'If' expression".to_string()),
-                }
-            }
+            } => vec![condition, then_expr, else_expr],
+            Expression::Apply {
+                func_expr,
+                arg_expr,
+            } => vec![func_expr, arg_expr],
+            Expression::Let { value, body, .. } => vec![value, body],
+            Expression::LetRec {
+                func_body, body, ..
+            } => vec![func_body, body],
+            Expression::Call { args, .. } => args.iter().collect(),
         }
     }
+
+    /// The names of the variables used in the expression without being bound by an enclosing `func` or `let`.
+    pub fn free_variables(&self) -> HashSet<String> {
+        let mut free = HashSet::new();
+        collect_free_variables(self, &mut Vec::new(), &mut free);
+        free
+    }
+
+    /// Evaluates the expression with a default [`Evaluator`].
+    pub fn eval(&self) -> Result<Value, EvalError> {
+        Evaluator::new().eval(self)
+    }
+
+    /// Evaluates the expression with its free variables taken from `bindings`.
+    pub fn eval_with(&self, bindings: &Bindings) -> Result<Value, EvalError> {
+        Evaluator::new().eval_with(self, bindings)
+    }
+
+    /// Evaluates the expression with the given integer overflow behaviour.
+    pub fn eval_with_arithmetic(&self, arithmetic: Arithmetic) -> Result<Value, EvalError> {
+        Evaluator::new().with_arithmetic(arithmetic).eval(self)
+    }
+
+    /// Evaluates the expression with the given kind of integer division.
+    pub fn eval_with_division(&self, division: Division) -> Result<Value, EvalError> {
+        Evaluator::new().with_division(division).eval(self)
+    }
+
+    /// Evaluates the expression with float literals read as the given kind of number.
+    pub fn eval_with_floats(&self, floats: Floats) -> Result<Value, EvalError> {
+        Evaluator::new().with_floats(floats).eval(self)
+    }
 }
 
-// Helper function to substitute a parameter with an argument in an expression
-fn substitute(expr: &Expression, param: &str, arg: &Expression) -> Expression {
+// Helper function to substitute variables with expressions, all at once. Bound
+// variables that would capture a free variable of a replacement are renamed.
+pub(crate) fn substitute(
+    expr: &Expression,
+    replacements: &HashMap<String, Expression>,
+) -> Expression {
     match expr {
-        Expression::Integer(_) | Expression::Boolean(_) => expr.clone(),
+        Expression::Integer(_)
+        | Expression::Float { .. }
+        | Expression::String(_)
+        | Expression::Boolean(_) => expr.clone(),
 
-        Expression::Variable(var_name) => {
-            if var_name == param {
-                arg.clone()
-            } else {
-                expr.clone()
+        Expression::Variable(var_name) => match replacements.get(var_name) {
+            Some(replacement) => replacement.clone(),
+            None => expr.clone(),
+        },
+
+        Expression::UnaryOp { op, child } => Expression::UnaryOp {
+            op: *op,
+            child: Box::new(substitute(child, replacements)),
+        },
+
+        Expression::BinaryOp { op, lhs, rhs } => Expression::BinaryOp {
+            op: *op,
+            lhs: Box::new(substitute(lhs, replacements)),
+            rhs: Box::new(substitute(rhs, replacements)),
+        },
+
+        Expression::If {
+            condition,
+            then_expr,
+            else_expr,
+        } => Expression::If {
+            condition: Box::new(substitute(condition, replacements)),
+            then_expr: Box::new(substitute(then_expr, replacements)),
+            else_expr: Box::new(substitute(else_expr, replacements)),
+        },
+
+        Expression::Apply {
+            func_expr,
+            arg_expr,
+        } => Expression::Apply {
+            func_expr: Box::new(substitute(func_expr, replacements)),
+            arg_expr: Box::new(substitute(arg_expr, replacements)),
+        },
+
+        Expression::Call { builtin, args } => Expression::Call {
+            builtin: *builtin,
+            args: args
+                .iter()
+                .map(|arg| substitute(arg, replacements))
+                .collect(),
+        },
+
+        Expression::Func { param, body } => {
+            let (param, mut bodies) = substitute_under_binder(param, &[body], replacements);
+            Expression::Func {
+                param,
+                body: Box::new(bodies.remove(0)),
             }
         }
 
-        Expression::UnaryOp { op, child } => {
-            Expression::UnaryOp {
-                op: *op,
-                child: Box::new(substitute(child, param, arg)),
+        Expression::Let { name, value, body } => {
+            let (name, mut bodies) = substitute_under_binder(name, &[body], replacements);
+            Expression::Let {
+                name,
+                value: Box::new(substitute(value, replacements)),
+                body: Box::new(bodies.remove(0)),
             }
         }
 
-        Expression::BinaryOp { op, lhs, rhs } => {
-            Expression::BinaryOp {
-                op: *op,
-                lhs: Box::new(substitute(lhs, param, arg)),
-                rhs: Box::new(substitute(rhs, param, arg)),
+        Expression::LetRec {
+            name,
+            param,
+            func_body,
+            body,
+        } => {
+            // The name is bound in the function and the body alike, so both are
+            // substituted together and any renaming applies to both
+            let func = Expression::Func {
+                param: param.clone(),
+                body: func_body.clone(),
+            };
+            let (name, mut bodies) = substitute_under_binder(name, &[&func, body], replacements);
+            let body = bodies.pop().unwrap();
+            let Some(Expression::Func {
+                param,
+                body: func_body,
+            }) = bodies.pop()
+            else {
+                unreachable!("substituting into a function gives a function")
+            };
+            Expression::LetRec {
+                name,
+                param,
+                func_body,
+                body: Box::new(body),
             }
         }
+    }
+}
 
-        _ => expr.clone(),
+// Helper function to substitute into `bodies`, in all of which `param` is
+// bound, and return the parameter and bodies to use in their place
+fn substitute_under_binder(
+    param: &str,
+    bodies: &[&Expression],
+    replacements: &HashMap<String, Expression>,
+) -> (String, Vec<Expression>) {
+    // The binder rebinds its parameter, so nothing below refers to the outer one
+    let mut body_free = HashSet::new();
+    for body in bodies {
+        body_free.extend(body.free_variables());
+    }
+    let mut inner: HashMap<String, Expression> = replacements
+        .iter()
+        .filter(|(name, _)| *name != param && body_free.contains(*name))
+        .map(|(name, replacement)| (name.clone(), replacement.clone()))
+        .collect();
+    let substitute_all = |inner: &HashMap<String, Expression>| {
+        bodies.iter().map(|body| substitute(body, inner)).collect()
+    };
+    if inner.is_empty() {
+        return (
+            param.to_string(),
+            bodies.iter().map(|&body| body.clone()).collect(),
+        );
     }
-}
\ No newline at end of file
+
+    let mut replacement_free = HashSet::new();
+    for replacement in inner.values() {
+        replacement_free.extend(replacement.free_variables());
+    }
+    if replacement_free.contains(param) {
+        // Substituting would capture a replacement's free variable, so rename
+        // the parameter to a name that is free in neither
+        let fresh = fresh_name(param, &body_free, &replacement_free);
+        inner.insert(param.to_string(), Expression::Variable(fresh.clone()));
+        (fresh, substitute_all(&inner))
+    } else {
+        (param.to_string(), substitute_all(&inner))
+    }
+}
+
+// Helper function to pick `base` followed by the smallest number that is not already in use
+fn fresh_name(base: &str, used: &HashSet<String>, also_used: &HashSet<String>) -> String {
+    (1..)
+        .map(|n| format!("{}{}", base, n))
+        .find(|name| !used.contains(name) && !also_used.contains(name))
+        .unwrap()
+}
+
+// Helper function to collect the free variables of `expr` that are not in `bound`
+fn collect_free_variables(expr: &Expression, bound: &mut Vec<String>, free: &mut HashSet<String>) {
+    match expr {
+        Expression::Integer(_)
+        | Expression::Float { .. }
+        | Expression::String(_)
+        | Expression::Boolean(_) => {}
+        Expression::Variable(name) => {
+            if !bound.contains(name) {
+                free.insert(name.clone());
+            }
+        }
+        Expression::UnaryOp { child, .. } => collect_free_variables(child, bound, free),
+        Expression::BinaryOp { lhs, rhs, .. } => {
+            collect_free_variables(lhs, bound, free);
+            collect_free_variables(rhs, bound, free);
+        }
+        Expression::If {
+            condition,
+            then_expr,
+            else_expr,
+        } => {
+            collect_free_variables(condition, bound, free);
+            collect_free_variables(then_expr, bound, free);
+            collect_free_variables(else_expr, bound, free);
+        }
+        Expression::Apply {
+            func_expr,
+            arg_expr,
+        } => {
+            collect_free_variables(func_expr, bound, free);
+            collect_free_variables(arg_expr, bound, free);
+        }
+        Expression::Func { param, body } => {
+            bound.push(param.clone());
+            collect_free_variables(body, bound, free);
+            bound.pop();
+        }
+        Expression::Let { name, value, body } => {
+            // The name is only in scope in the body
+            collect_free_variables(value, bound, free);
+            bound.push(name.clone());
+            collect_free_variables(body, bound, free);
+            bound.pop();
+        }
+        Expression::Call { args, .. } => {
+            for arg in args {
+                collect_free_variables(arg, bound, free);
+            }
+        }
+        Expression::LetRec {
+            name,
+            param,
+            func_body,
+            body,
+        } => {
+            // The name is in scope in the function as well as the body
+            bound.push(name.clone());
+            bound.push(param.clone());
+            collect_free_variables(func_body, bound, free);
+            bound.pop();
+            collect_free_variables(body, bound, free);
+            bound.pop();
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use rust_decimal::Decimal;
+
+/// The integers that programs compute with: `i64` by default, or the unbounded
+/// `BigInt` of num-bigint with the `bigint` feature, in which case integer
+/// literals may be any length and arithmetic never overflows.
+///
+/// The one limit with `bigint` is on powers, which grow fastest: a `^` or
+/// `pow` whose result could have more than 2^20 bits, going by the size of the
+/// base, is an overflow rather than a denial of service.
+pub type Int = Repr;
+
+#[cfg(not(feature = "bigint"))]
+type Repr = i64;
+
+#[cfg(feature = "bigint")]
+type Repr = num_bigint::BigInt;
+
+// A copy of `a`. Code shared by both integer types copies an `Int` with this
+// rather than `clone`, which clippy flags for `i64` alone.
+#[cfg_attr(not(feature = "bigint"), allow(clippy::clone_on_copy))]
+pub(crate) fn dup(a: &Int) -> Int {
+    a.clone()
+}
+
+// The integer `value`, whichever type integers are
+#[allow(clippy::useless_conversion)]
+pub(crate) fn int(value: i64) -> Int {
+    Int::from(value)
+}
+
+// Converts `a` to an `i64`, returning None if it does not fit
+#[cfg(not(feature = "bigint"))]
+pub(crate) fn to_i64(a: &Int) -> Option<i64> {
+    Some(*a)
+}
+
+#[cfg(feature = "bigint")]
+pub(crate) fn to_i64(a: &Int) -> Option<i64> {
+    i64::try_from(a).ok()
+}
+
+// Converts `a` to the nearest float, which is infinite if `a` is too large
+#[cfg(not(feature = "bigint"))]
+pub(crate) fn to_f64(a: &Int) -> f64 {
+    *a as f64
+}
+
+#[cfg(feature = "bigint")]
+pub(crate) fn to_f64(a: &Int) -> f64 {
+    a.to_string().parse().expect("integers are valid floats")
+}
+
+// Converts the float `value` to the integer it equals, returning None if it is
+// not a whole number or does not fit
+#[cfg(not(feature = "bigint"))]
+pub(crate) fn from_f64(value: f64) -> Option<Int> {
+    // -2^63 is a float, so every whole float from it up to but not including 2^63 fits
+    let fits = value >= i64::MIN as f64 && value < -(i64::MIN as f64);
+    (value.fract() == 0.0 && fits).then_some(value as i64)
+}
+
+#[cfg(feature = "bigint")]
+pub(crate) fn from_f64(value: f64) -> Option<Int> {
+    if value.fract() != 0.0 {
+        return None;
+    }
+    // A whole float is its 53 bit mantissa shifted left, or right by no more
+    // than its trailing zeros
+    let bits = value.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i64 - 1075;
+    let mantissa = Int::from(bits & ((1 << 52) - 1) | 1 << 52);
+    let magnitude = if exponent >= 0 {
+        mantissa << exponent as usize
+    } else {
+        mantissa >> (-exponent) as usize
+    };
+    Some(if value < 0.0 { -magnitude } else { magnitude })
+}
+
+// Converts `a` to a decimal, returning None if it does not fit
+#[cfg(not(feature = "bigint"))]
+pub(crate) fn to_decimal(a: &Int) -> Option<Decimal> {
+    Some(Decimal::from(*a))
+}
+
+#[cfg(feature = "bigint")]
+pub(crate) fn to_decimal(a: &Int) -> Option<Decimal> {
+    a.to_string().parse().ok()
+}
+
+// The most bits the result of a power may have with the `bigint` feature
+#[cfg(feature = "bigint")]
+const MAX_POWER_BITS: u64 = 1 << 20;
+
+// Whether `base ^ exp` is small enough to compute. An i64 power is checked as
+// it is computed, so it always is.
+#[cfg(not(feature = "bigint"))]
+pub(crate) fn power_in_bounds(_base: &Int, _exp: u64) -> bool {
+    true
+}
+
+// A power has at most as many bits as the base times the exponent, and powers
+// of 0, 1 and -1, the only integers of at most one bit, never grow
+#[cfg(feature = "bigint")]
+pub(crate) fn power_in_bounds(base: &Int, exp: u64) -> bool {
+    base.bits() <= 1 || base.bits().saturating_mul(exp) <= MAX_POWER_BITS
+}
+
+// Converts `a` to a `u64`, returning None if it is negative or does not fit
+#[cfg(not(feature = "bigint"))]
+pub(crate) fn to_u64(a: &Int) -> Option<u64> {
+    u64::try_from(*a).ok()
+}
+
+#[cfg(feature = "bigint")]
+pub(crate) fn to_u64(a: &Int) -> Option<u64> {
+    u64::try_from(a).ok()
+}
+
+// The remainder of `a` divided by the non-zero `b`, with the sign of `a`. It
+// always fits, even for `i64::MIN` and -1, where it is 0.
+#[cfg(not(feature = "bigint"))]
+pub(crate) fn rem(a: &Int, b: &Int) -> Int {
+    a.wrapping_rem(*b)
+}
+
+#[cfg(feature = "bigint")]
+pub(crate) fn rem(a: &Int, b: &Int) -> Int {
+    a % b
+}
+
+// The remainder of `a` divided by the non-zero `b`, which is never negative
+#[cfg(not(feature = "bigint"))]
+pub(crate) fn rem_euclid(a: &Int, b: &Int) -> Int {
+    a.wrapping_rem_euclid(*b)
+}
+
+#[cfg(feature = "bigint")]
+pub(crate) fn rem_euclid(a: &Int, b: &Int) -> Int {
+    let r = a % b;
+    match (r < int(0), b < &int(0)) {
+        (true, true) => r - b,
+        (true, false) => r + b,
+        (false, _) => r,
+    }
+}
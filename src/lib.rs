@@ -0,0 +1,31 @@
+pub mod error;
+pub mod evaluator;
+pub mod expression;
+pub mod integer;
+pub mod parser;
+pub mod rational;
+pub mod span;
+pub mod value;
+
+#[cfg(test)]
+mod test;
+
+pub use crate::error::{ConversionError, Error, EvalError, Expected, LexError, ParseError, Type};
+pub use crate::evaluator::{Arithmetic, Division, Evaluator, Floats, Unbound};
+pub use crate::expression::{
+    BinaryOperator, Builtin, Canonical, Expression, Operator, UnaryOperator,
+};
+pub use crate::integer::Int;
+pub use crate::parser::{
+    lex, lex_spanned, lex_spanned_with_trivia, lex_with_trivia, LexItem, Parser, Syntax, Token,
+};
+pub use crate::rational::Rational;
+pub use crate::span::{NodeId, Position, Span, Spans};
+pub use crate::value::{Bindings, Closure, Env, Value};
+pub use rust_decimal::Decimal;
+
+/// Parses `input` and evaluates the resulting expression.
+pub fn parse_and_eval(input: &str) -> Result<Value, Error> {
+    let (expression, spans) = Parser::new(input).parse_spanned()?;
+    Ok(Evaluator::new().eval_spanned(&expression, &spans)?)
+}
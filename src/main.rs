@@ -1,33 +1,47 @@
-use crate::parser::Parser;
-
-pub mod expression;
-pub mod parser;
-pub mod test;
+use arith_parser::{Evaluator, Parser, Position};
 
 fn main() {
+    // A path argument evaluates the program in that file instead of starting the prompt
+    if let Some(path) = std::env::args().nth(1) {
+        match std::fs::read_to_string(&path) {
+            Ok(program) => evaluate(&program),
+            Err(error) => eprintln!("Error reading {}: {}", path, error),
+        }
+        return;
+    }
+
     loop {
         println!("Enter an expression to evaluate:");
         let mut input = String::new();
-        std::io::stdin().read_line(&mut input).unwrap();
+        if std::io::stdin().read_line(&mut input).unwrap() == 0 {
+            break;
+        }
 
-        let mut prog = Parser::new(input.trim());
-        match prog.parse() {
-            Ok(parsed) => {
-                match parsed.eval() {
-                    Ok(result) => {
-                        println!("-----");
-                        println!("Problem: {}", format!("{}", &parsed));
-                        println!("Answer: {}", format!("{}", &result));
-                        println!("-----");
-                    }
-                    Err(error) => {
-                        eprintln!("Error evaluating expression: {}", error);
-                    }
-                }
-            }
-            Err(error) => {
-                eprintln!("Error parsing expression: {}", error);
+        evaluate(input.trim());
+    }
+}
+
+fn evaluate(program: &str) {
+    let mut prog = Parser::new(program);
+    match prog.parse_spanned() {
+        Ok((parsed, spans)) => match Evaluator::new().eval_spanned(&parsed, &spans) {
+            Ok(result) => {
+                println!("-----");
+                println!("Problem: {}", parsed);
+                println!("Answer: {}", result);
+                println!("-----");
             }
+            Err(error) => match error.span() {
+                Some(span) => eprintln!(
+                    "Error evaluating expression: {}: {}",
+                    Position::locate(program, span.start),
+                    error
+                ),
+                None => eprintln!("Error evaluating expression: {}", error),
+            },
+        },
+        Err(error) => {
+            eprintln!("Error parsing expression: {}", error);
         }
     }
 }
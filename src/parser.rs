@@ -1,357 +1,1030 @@
-use crate::expression::{BinaryOperator, Expression, UnaryOperator};
+use std::fmt::{Display, Error};
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use crate::error::{Expected, LexError, ParseError};
+use crate::evaluator::Arithmetic;
+use crate::expression::{BinaryOperator, Builtin, Expression, UnaryOperator};
+use crate::integer::{self, Int};
+use crate::span::{Position, Span, Spans};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum LexItem {
-    OpenParen,          // "("
-    CloseParen,         // ")"
-    Comma,              // ","
-    Integer(i64),             // "0", "1", "2", ...
-    Variable(String),         // "a", "b", "c", ...
-    Boolean(bool),            // "T" or "F"
-    If,               // "if"
-    Then,             // "then"
-    Else,             // "else"
-    Func,             // "func"
-    Apply,            // "apply"
-    BinaryOp(BinaryOperator), // "+", "-", "*", "/", "<", "=", "&", "|"
-    UnaryOp(UnaryOperator),   // "!"
-    Arrow,            // "=>"
+    OpenParen,    // "("
+    CloseParen,   // ")"
+    Comma,        // ","
+    Integer(Int), // "0", "1", "-2", ...
+    // "2.5", "-0.1", "6.02e23", ..., with the text it was written as
+    Float { value: f64, text: String },
+    String(String),           // "\"abc\"", "\"\"", ..., with its escapes resolved
+    Variable(String),         // "a", "x1", "my_var", "Total", ...
+    Boolean(bool),            // "T", "F", "true" or "false"
+    If,                       // "if"
+    Then,                     // "then"
+    Else,                     // "else"
+    Func,                     // "func"
+    Apply,                    // "apply"
+    Let,                      // "let"
+    Rec,                      // "rec"
+    In,                       // "in"
+    BinaryOp(BinaryOperator), // "+", "-", "*", "/", "%", "^", "<", ">", "<=", ">=", "=", "!=", "&", "|"
+    UnaryOp(UnaryOperator),   // "!" ("-" is lexed as a binary operator)
+    Arrow,                    // "=>"
+    Comment(String),          // "# ...", "// ..." or "/* ... */", only when lexing trivia
 }
 
-pub fn lex(input: &str) -> Result<Vec<LexItem>, String> {
-    let mut result = Vec::new();
+impl Display for LexItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match self {
+            LexItem::OpenParen => write!(f, "("),
+            LexItem::CloseParen => write!(f, ")"),
+            LexItem::Comma => write!(f, ","),
+            LexItem::Integer(value) => write!(f, "{}", value),
+            LexItem::Float { text, .. } => write!(f, "{}", text),
+            LexItem::String(text) => write_quoted(f, text),
+            LexItem::Variable(name) => write!(f, "{}", name),
+            LexItem::Boolean(value) => write!(f, "{}", if *value { "T" } else { "F" }),
+            LexItem::If => write!(f, "if"),
+            LexItem::Then => write!(f, "then"),
+            LexItem::Else => write!(f, "else"),
+            LexItem::Func => write!(f, "func"),
+            LexItem::Apply => write!(f, "apply"),
+            LexItem::Let => write!(f, "let"),
+            LexItem::Rec => write!(f, "rec"),
+            LexItem::In => write!(f, "in"),
+            LexItem::BinaryOp(op) => write!(f, "{}", op),
+            LexItem::UnaryOp(op) => write!(f, "{}", op),
+            LexItem::Arrow => write!(f, "=>"),
+            LexItem::Comment(text) => write!(f, "{}", text),
+        }
+    }
+}
 
-    let mut iterable = input.chars().peekable();
-    while let Some(&c) = iterable.peek() {
-        match c {
-            '0'..='9' => {
-                let mut value = String::new();
-                while let Some(&c) = iterable.peek() {
-                    match c {
-                        '0'..='9' => {
-                            value.push(c);
-                            iterable.next();
-                        }
-                        _ => break,
-                    }
-                }
-                result.push(LexItem::Integer(value.parse().unwrap()));
-            }
-            'a'..='z' => {
+/// A lexed item together with the bytes of the input it was read from.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Token {
+    pub item: LexItem,
+    pub span: Span,
+}
+
+pub fn lex(input: &str) -> Result<Vec<LexItem>, LexError> {
+    Ok(tokenize(input, false)?
+        .into_iter()
+        .map(|token| token.item)
+        .collect())
+}
+
+/// Like [`lex`], but keeps the span of each item.
+pub fn lex_spanned(input: &str) -> Result<Vec<Token>, LexError> {
+    tokenize(input, false)
+}
+
+/// Like [`lex`], but keeps comments as [`LexItem::Comment`] items, for tools
+/// such as formatters that need to reproduce them.
+pub fn lex_with_trivia(input: &str) -> Result<Vec<LexItem>, LexError> {
+    Ok(tokenize(input, true)?
+        .into_iter()
+        .map(|token| token.item)
+        .collect())
+}
+
+/// Like [`lex_with_trivia`], but keeps the span of each item, so comments can
+/// be put back where they were.
+pub fn lex_spanned_with_trivia(input: &str) -> Result<Vec<Token>, LexError> {
+    tokenize(input, true)
+}
+
+fn tokenize(input: &str, keep_trivia: bool) -> Result<Vec<Token>, LexError> {
+    let mut result: Vec<Token> = Vec::new();
+
+    let mut iterable = input.char_indices().peekable();
+    while let Some(&(start, c)) = iterable.peek() {
+        let item = match c {
+            '0'..='9' => lex_number(input, start, &mut iterable)?,
+            '"' => lex_string(input, start, &mut iterable)?,
+            c if c.is_alphabetic() || c == '_' => {
+                // Identifiers start with a letter or underscore and continue with
+                // letters, digits and underscores
                 let mut value = String::new();
-                while let Some(&c) = iterable.peek() {
-                    match c {
-                        'a'..='z' => {
-                            value.push(c);
-                            iterable.next();
-                        }
-                        _ => break,
+                while let Some(&(_, c)) = iterable.peek() {
+                    if c.is_alphanumeric() || c == '_' {
+                        value.push(c);
+                        iterable.next();
+                    } else {
+                        break;
                     }
                 }
                 match value.as_str() {
-                    "if" => result.push(LexItem::If),
-                    "then" => result.push(LexItem::Then),
-                    "else" => result.push(LexItem::Else),
-                    "func" => result.push(LexItem::Func),
-                    "apply" => result.push(LexItem::Apply),
-                    _ => result.push(LexItem::Variable(value)),
+                    "if" => LexItem::If,
+                    "then" => LexItem::Then,
+                    "else" => LexItem::Else,
+                    "func" => LexItem::Func,
+                    "apply" => LexItem::Apply,
+                    "let" => LexItem::Let,
+                    "rec" => LexItem::Rec,
+                    "in" => LexItem::In,
+                    "T" | "true" => LexItem::Boolean(true),
+                    "F" | "false" => LexItem::Boolean(false),
+                    _ => LexItem::Variable(value),
                 }
             }
-            'T' => {
-                result.push(LexItem::Boolean(true));
-                iterable.next();
-            }
-            'F' => {
-                result.push(LexItem::Boolean(false));
-                iterable.next();
-            }
             '+' => {
-                result.push(LexItem::BinaryOp(BinaryOperator::Add));
                 iterable.next();
+                LexItem::BinaryOp(BinaryOperator::Add)
             }
             '-' => {
-                result.push(LexItem::BinaryOp(BinaryOperator::Subtract));
                 iterable.next();
+                // A '-' directly before a digit is the sign of a literal, unless
+                // it follows an operand and so can only be a subtraction
+                let follows_operand = matches!(
+                    result
+                        .iter()
+                        .rev()
+                        .find(|token| !matches!(token.item, LexItem::Comment(_))),
+                    Some(Token {
+                        item: LexItem::Integer(_)
+                            | LexItem::Float { .. }
+                            | LexItem::String(_)
+                            | LexItem::Variable(_)
+                            | LexItem::Boolean(_)
+                            | LexItem::CloseParen,
+                        ..
+                    })
+                );
+                match iterable.peek() {
+                    Some(&(_, '0'..='9')) if !follows_operand => {
+                        lex_number(input, start, &mut iterable)?
+                    }
+                    _ => LexItem::BinaryOp(BinaryOperator::Subtract),
+                }
             }
             '*' => {
-                result.push(LexItem::BinaryOp(BinaryOperator::Multiply));
                 iterable.next();
+                LexItem::BinaryOp(BinaryOperator::Multiply)
             }
             '/' => {
-                result.push(LexItem::BinaryOp(BinaryOperator::Divide));
                 iterable.next();
+                match iterable.peek() {
+                    Some(&(_, '/')) => lex_line_comment(input, start, &mut iterable),
+                    Some(&(_, '*')) => lex_block_comment(input, start, &mut iterable)?,
+                    _ => LexItem::BinaryOp(BinaryOperator::Divide),
+                }
             }
+            '#' => lex_line_comment(input, start, &mut iterable),
             '<' => {
-                result.push(LexItem::BinaryOp(BinaryOperator::LessThan));
+                // Check for "<=" and "<"
                 iterable.next();
+                match iterable.peek() {
+                    Some(&(_, '=')) => {
+                        iterable.next();
+                        LexItem::BinaryOp(BinaryOperator::LessEqual)
+                    }
+                    _ => LexItem::BinaryOp(BinaryOperator::LessThan),
+                }
+            }
+            '>' => {
+                // Check for ">=" and ">"
+                iterable.next();
+                match iterable.peek() {
+                    Some(&(_, '=')) => {
+                        iterable.next();
+                        LexItem::BinaryOp(BinaryOperator::GreaterEqual)
+                    }
+                    _ => LexItem::BinaryOp(BinaryOperator::GreaterThan),
+                }
             }
             '!' => {
-                result.push(LexItem::UnaryOp(UnaryOperator::Not));
+                // Check for "!=" and "!"
                 iterable.next();
+                match iterable.peek() {
+                    Some(&(_, '=')) => {
+                        iterable.next();
+                        LexItem::BinaryOp(BinaryOperator::NotEquals)
+                    }
+                    _ => LexItem::UnaryOp(UnaryOperator::Not),
+                }
             }
             '=' => {
                 // Check for "=>" and "="
                 iterable.next();
-                if let Some(&c) = iterable.peek() {
-                    match c {
-                        '>' => {
-                            result.push(LexItem::Arrow);
-                            iterable.next();
-                        }
-                        _ => {
-                            result.push(LexItem::BinaryOp(BinaryOperator::Equals));
-                        }
+                match iterable.peek() {
+                    Some(&(_, '>')) => {
+                        iterable.next();
+                        LexItem::Arrow
                     }
+                    _ => LexItem::BinaryOp(BinaryOperator::Equals),
                 }
             }
+            '%' => {
+                iterable.next();
+                LexItem::BinaryOp(BinaryOperator::Remainder)
+            }
+            '^' => {
+                iterable.next();
+                LexItem::BinaryOp(BinaryOperator::Power)
+            }
             '&' => {
-                result.push(LexItem::BinaryOp(BinaryOperator::And));
                 iterable.next();
+                LexItem::BinaryOp(BinaryOperator::And)
             }
             '|' => {
-                result.push(LexItem::BinaryOp(BinaryOperator::Or));
                 iterable.next();
+                LexItem::BinaryOp(BinaryOperator::Or)
             }
             '(' => {
-                result.push(LexItem::OpenParen);
                 iterable.next();
+                LexItem::OpenParen
             }
             ',' => {
-                result.push(LexItem::Comma);
                 iterable.next();
+                LexItem::Comma
             }
             ')' => {
-                result.push(LexItem::CloseParen);
                 iterable.next();
+                LexItem::CloseParen
             }
-            ' ' | '\t' => {
-                // Skip whitespace
+            c if c.is_whitespace() => {
+                // Skip whitespace, including newlines
                 iterable.next();
+                continue;
             }
             _ => {
-                return Err(format!("unexpected character {}", c));
+                return Err(LexError::UnexpectedCharacter {
+                    found: c,
+                    span: Span::new(start, start + c.len_utf8()),
+                    position: Position::locate(input, start),
+                });
             }
+        };
+        if matches!(item, LexItem::Comment(_)) && !keep_trivia {
+            continue;
         }
+        let end = iterable.peek().map_or(input.len(), |&(index, _)| index);
+        result.push(Token {
+            item,
+            span: Span::new(start, end),
+        });
     }
     Ok(result)
 }
 
+/// The surface syntax for binary operators.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Syntax {
+    /// Operators are written before their parenthesised operands: `+(1, *(2, 3))`.
+    #[default]
+    Prefix,
+    /// Operators are written between their operands and bind by precedence:
+    /// `1 + 2 * 3`. Parentheses group subexpressions.
+    Infix,
+}
+
+// Binding power of prefix operators in infix syntax, higher than any binary operator but '^'
+pub(crate) const PREFIX_BINDING_POWER: u8 = 11;
+
+// Left and right binding powers of a binary operator in infix syntax. All
+// operators are left associative, so the right power is the higher one.
+pub(crate) fn infix_binding_power(op: BinaryOperator) -> (u8, u8) {
+    match op {
+        BinaryOperator::Or => (1, 2),
+        BinaryOperator::And => (3, 4),
+        BinaryOperator::LessThan
+        | BinaryOperator::GreaterThan
+        | BinaryOperator::LessEqual
+        | BinaryOperator::GreaterEqual
+        | BinaryOperator::Equals
+        | BinaryOperator::NotEquals => (5, 6),
+        BinaryOperator::Add | BinaryOperator::Subtract => (7, 8),
+        BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Remainder => (9, 10),
+        // Right associative, and tighter than prefix operators so "-x ^ 2" is "-(x ^ 2)"
+        BinaryOperator::Power => (13, 12),
+    }
+}
+
+// Lexes a comment running from `start` to the end of the line
+fn lex_line_comment(input: &str, start: usize, iterable: &mut Peekable<CharIndices>) -> LexItem {
+    while let Some(&(_, c)) = iterable.peek() {
+        if c == '\n' {
+            break;
+        }
+        iterable.next();
+    }
+    let end = iterable.peek().map_or(input.len(), |&(index, _)| index);
+    LexItem::Comment(input[start..end].to_string())
+}
+
+// Lexes a "/* ... */" comment starting at `start`, whose '/' has been consumed.
+// Block comments nest, so every "/*" inside needs its own "*/".
+fn lex_block_comment(
+    input: &str,
+    start: usize,
+    iterable: &mut Peekable<CharIndices>,
+) -> Result<LexItem, LexError> {
+    iterable.next();
+    let mut depth = 1;
+    while depth > 0 {
+        match iterable.next() {
+            Some((_, '/')) if matches!(iterable.peek(), Some(&(_, '*'))) => {
+                iterable.next();
+                depth += 1;
+            }
+            Some((_, '*')) if matches!(iterable.peek(), Some(&(_, '/'))) => {
+                iterable.next();
+                depth -= 1;
+            }
+            Some(_) => {}
+            None => {
+                return Err(LexError::UnterminatedComment {
+                    span: Span::new(start, input.len()),
+                    position: Position::locate(input, start),
+                })
+            }
+        }
+    }
+    let end = iterable.peek().map_or(input.len(), |&(index, _)| index);
+    Ok(LexItem::Comment(input[start..end].to_string()))
+}
+
+// Skips over a run of digits
+fn lex_digits(iterable: &mut Peekable<CharIndices>) {
+    while let Some(&(_, '0'..='9')) = iterable.peek() {
+        iterable.next();
+    }
+}
+
+// Lexes a number literal that begins at `start`, which may be a '-' sign. The
+// digits are an integer unless a fractional part or an exponent follows them,
+// as in "2.5", "1e9" or "6.02e-23", which make a float.
+fn lex_number(
+    input: &str,
+    start: usize,
+    iterable: &mut Peekable<CharIndices>,
+) -> Result<LexItem, LexError> {
+    lex_digits(iterable);
+    let mut is_float = false;
+    // A '.' is only part of the number if a digit follows it
+    let mut lookahead = iterable.clone();
+    if let (Some((_, '.')), Some(&(_, '0'..='9'))) = (lookahead.next(), lookahead.peek()) {
+        *iterable = lookahead;
+        lex_digits(iterable);
+        is_float = true;
+    }
+    // Likewise an 'e' only starts an exponent if digits follow it, after an optional sign
+    let mut lookahead = iterable.clone();
+    if let Some((_, 'e' | 'E')) = lookahead.next() {
+        if let Some(&(_, '+' | '-')) = lookahead.peek() {
+            lookahead.next();
+        }
+        if let Some(&(_, '0'..='9')) = lookahead.peek() {
+            *iterable = lookahead;
+            lex_digits(iterable);
+            is_float = true;
+        }
+    }
+    let end = iterable.peek().map_or(input.len(), |&(index, _)| index);
+    let literal = &input[start..end];
+    if is_float {
+        return match literal.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(LexItem::Float {
+                value,
+                text: literal.to_string(),
+            }),
+            _ => Err(LexError::FloatOutOfRange {
+                literal: literal.to_string(),
+                span: Span::new(start, end),
+                position: Position::locate(input, start),
+            }),
+        };
+    }
+    match literal.parse::<Int>() {
+        Ok(value) => Ok(LexItem::Integer(value)),
+        Err(_) => Err(LexError::IntegerOutOfRange {
+            literal: literal.to_string(),
+            span: Span::new(start, end),
+            position: Position::locate(input, start),
+        }),
+    }
+}
+
+// Lexes a string literal starting with the '"' at `start`. A backslash starts
+// one of the escapes \", \\, \n, \t, \r, \0 or \u{...}, the last of which
+// gives the character with the hexadecimal code between the braces.
+fn lex_string(
+    input: &str,
+    start: usize,
+    iterable: &mut Peekable<CharIndices>,
+) -> Result<LexItem, LexError> {
+    iterable.next();
+    let mut text = String::new();
+    loop {
+        match iterable.next() {
+            Some((_, '"')) => return Ok(LexItem::String(text)),
+            Some((escape_start, '\\')) => {
+                let c = match iterable.next() {
+                    Some((_, '"')) => Some('"'),
+                    Some((_, '\\')) => Some('\\'),
+                    Some((_, 'n')) => Some('\n'),
+                    Some((_, 't')) => Some('\t'),
+                    Some((_, 'r')) => Some('\r'),
+                    Some((_, '0')) => Some('\0'),
+                    Some((_, 'u')) => lex_unicode_escape(iterable),
+                    Some(_) => None,
+                    None => break,
+                };
+                match c {
+                    Some(c) => text.push(c),
+                    None => {
+                        let end = iterable.peek().map_or(input.len(), |&(index, _)| index);
+                        return Err(LexError::InvalidEscape {
+                            escape: input[escape_start..end].to_string(),
+                            span: Span::new(escape_start, end),
+                            position: Position::locate(input, escape_start),
+                        });
+                    }
+                }
+            }
+            Some((_, c)) => text.push(c),
+            None => break,
+        }
+    }
+    Err(LexError::UnterminatedString {
+        span: Span::new(start, input.len()),
+        position: Position::locate(input, start),
+    })
+}
+
+// Lexes the "{...}" of a \u{...} escape, returning None unless the braces
+// hold one to six hexadecimal digits that are the code of a character
+fn lex_unicode_escape(iterable: &mut Peekable<CharIndices>) -> Option<char> {
+    iterable.next_if(|&(_, c)| c == '{')?;
+    let mut digits = String::new();
+    while let Some((_, c)) = iterable.next_if(|&(_, c)| c.is_ascii_hexdigit()) {
+        digits.push(c);
+    }
+    iterable.next_if(|&(_, c)| c == '}')?;
+    if digits.is_empty() || digits.len() > 6 {
+        return None;
+    }
+    u32::from_str_radix(&digits, 16)
+        .ok()
+        .and_then(char::from_u32)
+}
+
+// Writes `text` as a string literal that lexes back to it, escaping quotes,
+// backslashes and control characters
+pub(crate) fn write_quoted(f: &mut std::fmt::Formatter<'_>, text: &str) -> Result<(), Error> {
+    write!(f, "\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\t' => write!(f, "\\t")?,
+            '\r' => write!(f, "\\r")?,
+            '\0' => write!(f, "\\0")?,
+            c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
 pub struct Parser {
-    tokens: Vec<LexItem>,
+    source: String,
+    tokens: Vec<Token>,
     current: usize,
+    lex_error: Option<LexError>,
+    syntax: Syntax,
+    // Spans of the nodes parsed so far, in the order they were completed
+    spans: Vec<Span>,
 }
 
 impl Parser {
+    /// Creates a parser for `program`. If the program cannot be lexed, the
+    /// error is reported by the first call to [`Parser::parse`].
     pub fn new(program: &str) -> Self {
-        let tokens = lex(program).unwrap_or_else(|err| {
-            eprintln!("Error during lexing: {}", err);
-            Vec::new()
-        });
+        match Parser::try_new(program) {
+            Ok(parser) => parser,
+            Err(error) => Parser {
+                source: program.to_string(),
+                tokens: Vec::new(),
+                current: 0,
+                lex_error: Some(error),
+                syntax: Syntax::default(),
+                spans: Vec::new(),
+            },
+        }
+    }
+
+    /// Creates a parser for `program`, failing straight away if it cannot be lexed.
+    pub fn try_new(program: &str) -> Result<Self, LexError> {
+        let tokens = tokenize(program, false)?;
 
-        Parser { tokens, current: 0 }
+        Ok(Parser {
+            source: program.to_string(),
+            tokens,
+            current: 0,
+            lex_error: None,
+            syntax: Syntax::default(),
+            spans: Vec::new(),
+        })
     }
 
-    pub fn parse(&mut self) -> Result<Expression, String> {
-        self.parse_expression()
+    /// Switches the parser to the given surface syntax.
+    pub fn with_syntax(mut self, syntax: Syntax) -> Self {
+        self.syntax = syntax;
+        self
     }
 
-    fn parse_expression(&mut self) -> Result<Expression, String> {
+    /// Parses the whole program as a single expression. Any tokens left over
+    /// after the expression are an error.
+    pub fn parse(&mut self) -> Result<Expression, ParseError> {
+        let (expression, _) = self.parse_prefix()?;
         if let Some(token) = self.tokens.get(self.current) {
-            match token {
-                LexItem::Integer(value) => {
-                    self.current += 1;
-                    Ok(Expression::Integer(*value))
-                }
-                LexItem::Variable(name) => {
-                    self.current += 1;
-                    Ok(Expression::Variable(name.clone()))
+            return Err(ParseError::TrailingInput {
+                found: token.item.clone(),
+                span: token.span,
+                position: Position::locate(&self.source, token.span.start),
+            });
+        }
+        Ok(expression)
+    }
+
+    /// Like [`Parser::parse`], but also returns the source span of every node
+    /// of the expression.
+    pub fn parse_spanned(&mut self) -> Result<(Expression, Spans), ParseError> {
+        let expression = self.parse()?;
+        let mut spans = Vec::with_capacity(self.spans.len());
+        preorder_spans(&expression, &self.spans, &mut 0, &mut spans);
+        Ok((expression, Spans::new(spans)))
+    }
+
+    /// Parses the next expression and returns it along with the source text
+    /// that has not been consumed yet. Calling it again continues from there.
+    pub fn parse_prefix(&mut self) -> Result<(Expression, &str), ParseError> {
+        if let Some(error) = &self.lex_error {
+            return Err(ParseError::Lex(error.clone()));
+        }
+        self.spans.clear();
+        let expression = self.parse_expression()?;
+        let rest = match self.tokens.get(self.current) {
+            Some(token) => &self.source[token.span.start..],
+            None => "",
+        };
+        Ok((expression, rest))
+    }
+
+    // The offset where the current token starts
+    fn start(&self) -> usize {
+        self.tokens
+            .get(self.current)
+            .map_or(self.source.len(), |token| token.span.start)
+    }
+
+    // Records the span of a node that began at `start` and ends with the last
+    // token consumed, and returns the node
+    fn finish(&mut self, start: usize, expression: Expression) -> Expression {
+        let end = self.tokens[self.current - 1].span.end;
+        self.spans.push(Span::new(start, end));
+        expression
+    }
+
+    fn peek(&self) -> Option<&LexItem> {
+        self.tokens.get(self.current).map(|token| &token.item)
+    }
+
+    fn peek_next(&self) -> Option<&LexItem> {
+        self.tokens.get(self.current + 1).map(|token| &token.item)
+    }
+
+    /// Builds the error for the current token not being any of `expected`.
+    fn unexpected(&self, expected: &[Expected]) -> ParseError {
+        match self.tokens.get(self.current) {
+            Some(token) => ParseError::UnexpectedToken {
+                found: token.item.clone(),
+                span: token.span,
+                position: Position::locate(&self.source, token.span.start),
+                expected: expected.to_vec(),
+            },
+            None => ParseError::UnexpectedEof {
+                offset: self.source.len(),
+                position: Position::locate(&self.source, self.source.len()),
+                expected: expected.to_vec(),
+            },
+        }
+    }
+
+    fn parse_expression(&mut self) -> Result<Expression, ParseError> {
+        if self.syntax == Syntax::Infix {
+            return self.parse_infix_expression(0);
+        }
+
+        match self.peek() {
+            Some(LexItem::UnaryOp(op)) => self.parse_unary_expression(*op),
+            // "-(a, b)" is a subtraction, while "-x" and "-(x)" are negations
+            Some(LexItem::BinaryOp(BinaryOperator::Subtract))
+                if self.peek_next() != Some(&LexItem::OpenParen) =>
+            {
+                self.parse_unary_expression(UnaryOperator::Negate)
+            }
+            Some(LexItem::BinaryOp(op)) => self.parse_binary_expression(*op),
+            _ => self.parse_atom(),
+        }
+    }
+
+    /// Parses the expressions that are written the same way in every syntax.
+    fn parse_atom(&mut self) -> Result<Expression, ParseError> {
+        let start = self.start();
+        match self.peek() {
+            Some(LexItem::Integer(value)) => {
+                let value = integer::dup(value);
+                self.current += 1;
+                Ok(self.finish(start, Expression::Integer(value)))
+            }
+            Some(LexItem::Float { value, text }) => {
+                let (value, text) = (*value, text.clone());
+                self.current += 1;
+                Ok(self.finish(start, Expression::Float { value, text }))
+            }
+            Some(LexItem::String(text)) => {
+                let text = text.clone();
+                self.current += 1;
+                Ok(self.finish(start, Expression::String(text)))
+            }
+            // A built-in function's name followed by '(' is a call, otherwise it is a variable
+            Some(LexItem::Variable(name)) if self.peek_next() == Some(&LexItem::OpenParen) => {
+                match Builtin::from_name(name) {
+                    Some(builtin) => self.parse_call_expression(builtin),
+                    None => {
+                        let name = name.clone();
+                        self.current += 1;
+                        Ok(self.finish(start, Expression::Variable(name)))
+                    }
                 }
-                LexItem::Boolean(value) => {
+            }
+            Some(LexItem::Variable(name)) => {
+                let name = name.clone();
+                self.current += 1;
+                Ok(self.finish(start, Expression::Variable(name)))
+            }
+            Some(LexItem::Boolean(value)) => {
+                let value = *value;
+                self.current += 1;
+                Ok(self.finish(start, Expression::Boolean(value)))
+            }
+            Some(LexItem::Func) => self.parse_func_expression(),
+            Some(LexItem::Apply) => self.parse_apply_expression(),
+            Some(LexItem::If) => self.parse_if_expression(),
+            Some(LexItem::Let) => self.parse_let_expression(),
+
+            _ => Err(self.unexpected(&[Expected::Expression])),
+        }
+    }
+
+    /// Parses an infix expression whose operators all bind tighter than `min_power`.
+    fn parse_infix_expression(&mut self, min_power: u8) -> Result<Expression, ParseError> {
+        let start = self.start();
+        let lhs = self.parse_infix_operand()?;
+        self.parse_infix_operators(start, lhs, min_power)
+    }
+
+    /// Parses the operators and operands that follow `lhs`, which began at
+    /// `start`, as long as the operators bind tighter than `min_power`.
+    fn parse_infix_operators(
+        &mut self,
+        start: usize,
+        mut lhs: Expression,
+        min_power: u8,
+    ) -> Result<Expression, ParseError> {
+        while let Some(LexItem::BinaryOp(op)) = self.peek() {
+            let op = *op;
+            let (left_power, right_power) = infix_binding_power(op);
+            if left_power < min_power {
+                break;
+            }
+            self.current += 1;
+
+            let rhs = self.parse_infix_expression(right_power)?;
+            lhs = self.finish(
+                start,
+                Expression::BinaryOp {
+                    op,
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                },
+            );
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_infix_operand(&mut self) -> Result<Expression, ParseError> {
+        let start = self.start();
+        // A negative literal as the base of '^' is the negation of the power,
+        // like any other negated base, so "-2 ^ 2" is -4 just as "-x ^ 2" is -(x ^ 2)
+        if let Some(base) = self.negated_power_base() {
+            self.current += 1;
+            // The literal's sign is its first byte, and the base is the rest
+            let base = self.finish(start + 1, base);
+            let child = self.parse_infix_operators(start + 1, base, PREFIX_BINDING_POWER)?;
+            return Ok(self.finish(
+                start,
+                Expression::UnaryOp {
+                    op: UnaryOperator::Negate,
+                    child: Box::new(child),
+                },
+            ));
+        }
+        match self.peek() {
+            Some(LexItem::OpenParen) => {
+                self.current += 1;
+                let inner = self.parse_infix_expression(0)?;
+
+                // Expect a closing parenthesis ')' after the grouped expression
+                if let Some(LexItem::CloseParen) = self.peek() {
                     self.current += 1;
-                    Ok(Expression::Boolean(*value))
+                } else {
+                    return Err(self.unexpected(&[Expected::BinaryOperator, Expected::CloseParen]));
                 }
-                LexItem::UnaryOp(op) => self.parse_unary_expression(op.clone()),
-                LexItem::BinaryOp(op) => self.parse_binary_expression(op.clone()),
-                LexItem::Func => self.parse_func_expression(),
-                LexItem::Apply => self.parse_apply_expression(),
-                LexItem::If => self.parse_if_expression(),
-
-                _ => Err("Expected expression".to_string()),
+                Ok(inner)
             }
-        } else {
-            Err("Unexpected end of input".to_string())
+            Some(LexItem::UnaryOp(op)) => {
+                let op = *op;
+                self.current += 1;
+                let child = self.parse_infix_expression(PREFIX_BINDING_POWER)?;
+                Ok(self.finish(
+                    start,
+                    Expression::UnaryOp {
+                        op,
+                        child: Box::new(child),
+                    },
+                ))
+            }
+            Some(LexItem::BinaryOp(BinaryOperator::Subtract)) => {
+                self.current += 1;
+                let child = self.parse_infix_expression(PREFIX_BINDING_POWER)?;
+                Ok(self.finish(
+                    start,
+                    Expression::UnaryOp {
+                        op: UnaryOperator::Negate,
+                        child: Box::new(child),
+                    },
+                ))
+            }
+            _ => self.parse_atom(),
         }
     }
 
-    fn parse_unary_expression(&mut self, op: UnaryOperator) -> Result<Expression, String> {
+    /// The magnitude of the current token, if it is a literal written with a
+    /// '-' sign, including "-0", and followed by '^'.
+    fn negated_power_base(&self) -> Option<Expression> {
+        let token = self.tokens.get(self.current)?;
+        if self.peek_next() != Some(&LexItem::BinaryOp(BinaryOperator::Power))
+            || !self.source[token.span.start..].starts_with('-')
+        {
+            return None;
+        }
+        match &token.item {
+            // The magnitude of the smallest i64 does not fit, so it stays the base
+            LexItem::Integer(value) => Arithmetic::Checked.negate(value).map(Expression::Integer),
+            LexItem::Float { value, text } => Some(Expression::Float {
+                value: -value,
+                text: text[1..].to_string(),
+            }),
+            _ => None,
+        }
+    }
+
+    fn parse_unary_expression(&mut self, op: UnaryOperator) -> Result<Expression, ParseError> {
+        let start = self.start();
         self.current += 1;
         let child = self.parse_expression()?;
-        Ok(Expression::UnaryOp {
-            op,
-            child: Box::new(child),
-        })
+        Ok(self.finish(
+            start,
+            Expression::UnaryOp {
+                op,
+                child: Box::new(child),
+            },
+        ))
     }
 
-    fn parse_binary_expression(&mut self, op: BinaryOperator) -> Result<Expression, String> {
+    fn parse_binary_expression(&mut self, op: BinaryOperator) -> Result<Expression, ParseError> {
+        let start = self.start();
+
         // Expect a binary operator
-        if let Some(LexItem::BinaryOp(_)) = self.tokens.get(self.current) {
+        if let Some(LexItem::BinaryOp(_)) = self.peek() {
             self.current += 1;
         } else {
-            return Err("Expected a binary operator".to_string());
+            return Err(self.unexpected(&[Expected::BinaryOperator]));
         }
 
-        // Expect an opening parenthesis '('
-        if let Some(LexItem::OpenParen) = self.tokens.get(self.current) {
+        // Expect an opening parenthesis '('. Parentheses are required for binary operations.
+        if let Some(LexItem::OpenParen) = self.peek() {
             self.current += 1;
+        } else {
+            return Err(self.unexpected(&[Expected::OpenParen]));
+        }
 
-            // Parse the left-hand side (lhs) expression
-            let lhs = self.parse_expression()?;
+        // Parse the left-hand side (lhs) expression
+        let lhs = self.parse_expression()?;
 
-            // Expect a comma ',' after the lhs
-            if let Some(LexItem::Comma) = self.tokens.get(self.current) {
+        // Expect a comma ',' after the lhs, or a closing parenthesis ')' for "-(x)"
+        match self.peek() {
+            Some(LexItem::Comma) => self.current += 1,
+            Some(LexItem::CloseParen) if op == BinaryOperator::Subtract => {
                 self.current += 1;
-            } else {
-                return Err("Expected ',' after left operand of binary expression".to_string());
+                return Ok(self.finish(
+                    start,
+                    Expression::UnaryOp {
+                        op: UnaryOperator::Negate,
+                        child: Box::new(lhs),
+                    },
+                ));
             }
-
-            // Parse the right-hand side (rhs) expression
-            let rhs = self.parse_expression()?;
-
-            // Expect a closing parenthesis ')' after the rhs
-            if let Some(LexItem::CloseParen) = self.tokens.get(self.current) {
-                self.current += 1;
-            } else {
-                return Err("Expected closing parenthesis ')'".to_string());
+            _ if op == BinaryOperator::Subtract => {
+                return Err(self.unexpected(&[Expected::Comma, Expected::CloseParen]))
             }
+            _ => return Err(self.unexpected(&[Expected::Comma])),
+        }
 
-            // Construct the BinaryOp expression
-            let binary_expr = Expression::BinaryOp {
-                op,
-                lhs: Box::new(lhs),
-                rhs: Box::new(rhs),
-            };
+        // Parse the right-hand side (rhs) expression
+        let rhs = self.parse_expression()?;
 
-            Ok(binary_expr)
+        // Expect a closing parenthesis ')' after the rhs
+        if let Some(LexItem::CloseParen) = self.peek() {
+            self.current += 1;
         } else {
-            Err(
-                "Expected opening parenthesis '('. Parentheses are required for binary operations."
-                    .to_string(),
-            )
+            return Err(self.unexpected(&[Expected::CloseParen]));
         }
+
+        // Construct the BinaryOp expression
+        let binary_expr = Expression::BinaryOp {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        };
+
+        Ok(self.finish(start, binary_expr))
     }
 
-    fn parse_func_expression(&mut self) -> Result<Expression, String> {
+    fn parse_func_expression(&mut self) -> Result<Expression, ParseError> {
+        let start = self.start();
+
         // Expect the "func" keyword
-        if let Some(LexItem::Func) = self.tokens.get(self.current) {
+        if let Some(LexItem::Func) = self.peek() {
             self.current += 1;
         } else {
-            return Err("Expected 'func' keyword".to_string());
+            return Err(self.unexpected(&[Expected::Func]));
         }
 
-        // Expect a variable name
-        let param_name = match self.tokens.get(self.current) {
-            Some(LexItem::Variable(name)) => {
+        let (param_name, body_expr) = self.parse_func_rest(start)?;
+
+        // Construct the Func expression
+        let func_expr = Expression::Func {
+            param: param_name,
+            body: Box::new(body_expr),
+        };
+
+        Ok(self.finish(start, func_expr))
+    }
+
+    /// Parses the parameters, "=>" and body that follow the "func" at `start`,
+    /// and returns the first parameter and the body. Any further parameters are
+    /// curried, so "func x, y => b" has the body "func y => b".
+    fn parse_func_rest(&mut self, start: usize) -> Result<(String, Expression), ParseError> {
+        // Expect one or more comma-separated variable names
+        let mut params = Vec::new();
+        loop {
+            match self.peek() {
+                Some(LexItem::Variable(name)) => {
+                    params.push(name.clone());
+                    self.current += 1;
+                }
+                _ => return Err(self.unexpected(&[Expected::Variable])),
+            }
+            if let Some(LexItem::Comma) = self.peek() {
                 self.current += 1;
-                name.clone()
+            } else {
+                break;
             }
-            _ => return Err("Expected variable name as function parameter".to_string()),
-        };
+        }
 
         // Expect the "=>" arrow
-        if let Some(LexItem::Arrow) = self.tokens.get(self.current) {
+        if let Some(LexItem::Arrow) = self.peek() {
             self.current += 1;
         } else {
-            return Err("Expected '=>' arrow after function parameter".to_string());
+            return Err(self.unexpected(&[Expected::Comma, Expected::Arrow]));
         }
 
         // Parse the body expression
-        let body_expr = self.parse_expression()?;
+        let mut body_expr = self.parse_expression()?;
 
-        // Construct the Func expression
-        let func_expr = Expression::Func {
-            param: param_name,
-            body: Box::new(body_expr),
-        };
+        // Wrap the body in a function for each parameter after the first,
+        // innermost first. Each spans the whole function, since the text from
+        // a later parameter on, such as "y => b", is not an expression.
+        let first_param = params.remove(0);
+        while let Some(param) = params.pop() {
+            body_expr = self.finish(
+                start,
+                Expression::Func {
+                    param,
+                    body: Box::new(body_expr),
+                },
+            );
+        }
 
-        Ok(func_expr)
+        Ok((first_param, body_expr))
     }
 
-    fn parse_apply_expression(&mut self) -> Result<Expression, String> {
+    fn parse_apply_expression(&mut self) -> Result<Expression, ParseError> {
+        let start = self.start();
+
         // Expect the "apply" keyword
-        if let Some(LexItem::Apply) = self.tokens.get(self.current) {
+        if let Some(LexItem::Apply) = self.peek() {
             self.current += 1;
         } else {
-            return Err("Expected 'apply' keyword".to_string());
+            return Err(self.unexpected(&[Expected::Apply]));
         }
 
-        // Expect an opening parenthesis '('
-        if let Some(LexItem::OpenParen) = self.tokens.get(self.current) {
+        // Expect an opening parenthesis '('. Parentheses are required for apply expression.
+        if let Some(LexItem::OpenParen) = self.peek() {
             self.current += 1;
         } else {
-            return Err(
-                "Expected opening parenthesis '('. Parentheses are required for apply expression"
-                    .to_string(),
-            );
+            return Err(self.unexpected(&[Expected::OpenParen]));
         }
 
         // Parse the function expression
         let func_expr = self.parse_expression()?;
 
         // Expect a comma ','
-        if let Some(LexItem::Comma) = self.tokens.get(self.current) {
+        if let Some(LexItem::Comma) = self.peek() {
             self.current += 1;
         } else {
-            return Err("Expected comma ',' after function expression".to_string());
+            return Err(self.unexpected(&[Expected::Comma]));
         }
 
-        // Parse the argument expression
-        let arg_expr = self.parse_expression()?;
+        // Parse the argument expressions, applying the function to one at a
+        // time so that "apply(f, x, y)" is "apply(apply(f, x), y)"
+        let mut apply_expr = Expression::Apply {
+            func_expr: Box::new(func_expr),
+            arg_expr: Box::new(self.parse_expression()?),
+        };
+        let mut partial_spans = Vec::new();
+        while let Some(LexItem::Comma) = self.peek() {
+            partial_spans.push(self.spans.len());
+            apply_expr = self.finish(start, apply_expr);
+            self.current += 1;
+            apply_expr = Expression::Apply {
+                func_expr: Box::new(apply_expr),
+                arg_expr: Box::new(self.parse_expression()?),
+            };
+        }
 
         // Expect a closing parenthesis ')'
-        if let Some(LexItem::CloseParen) = self.tokens.get(self.current) {
+        if let Some(LexItem::CloseParen) = self.peek() {
             self.current += 1;
         } else {
-            return Err(
-                "Expected closing parenthesis ')'. Parentheses are required for apply expression"
-                    .to_string(),
-            );
+            return Err(self.unexpected(&[Expected::Comma, Expected::CloseParen]));
         }
 
-        // Construct the Apply expression
-        let apply_expr = Expression::Apply {
-            func_expr: Box::new(func_expr),
-            arg_expr: Box::new(arg_expr),
-        };
-
+        // The partial applications span the whole call, since the text up to
+        // one of their last arguments, such as "apply(f, x", is not an expression
+        let apply_expr = self.finish(start, apply_expr);
+        let whole = self.spans[self.spans.len() - 1];
+        for index in partial_spans {
+            self.spans[index] = whole;
+        }
         Ok(apply_expr)
     }
 
-    fn parse_if_expression(&mut self) -> Result<Expression, String> {
+    fn parse_if_expression(&mut self) -> Result<Expression, ParseError> {
+        let start = self.start();
+
         // Expect the "if" keyword
-        if let Some(LexItem::If) = self.tokens.get(self.current) {
+        if let Some(LexItem::If) = self.peek() {
             self.current += 1;
         } else {
-            return Err("Expected 'if' keyword".to_string());
+            return Err(self.unexpected(&[Expected::If]));
         }
 
         // Parse the condition expression
         let condition_expr = self.parse_expression()?;
 
         // Expect the "then" keyword
-        if let Some(LexItem::Then) = self.tokens.get(self.current) {
+        if let Some(LexItem::Then) = self.peek() {
             self.current += 1;
         } else {
-            return Err("Expected 'then' keyword".to_string());
+            return Err(self.unexpected(&[Expected::Then]));
         }
 
         // Parse the true branch expression
         let true_expr = self.parse_expression()?;
 
         // Expect the "else" keyword
-        if let Some(LexItem::Else) = self.tokens.get(self.current) {
+        if let Some(LexItem::Else) = self.peek() {
             self.current += 1;
         } else {
-            return Err("Expected 'else' keyword".to_string());
+            return Err(self.unexpected(&[Expected::Else]));
         }
 
         // Parse the false branch expression
@@ -364,6 +1037,162 @@ impl Parser {
             else_expr: Box::new(false_expr),
         };
 
-        Ok(if_expr)
+        Ok(self.finish(start, if_expr))
+    }
+
+    fn parse_let_expression(&mut self) -> Result<Expression, ParseError> {
+        let start = self.start();
+
+        // Expect the "let" keyword
+        if let Some(LexItem::Let) = self.peek() {
+            self.current += 1;
+        } else {
+            return Err(self.unexpected(&[Expected::Let]));
+        }
+
+        // "let rec" binds the name in its own value as well as in the body
+        let recursive = self.peek() == Some(&LexItem::Rec);
+        if recursive {
+            self.current += 1;
+        }
+
+        // Expect the name being bound
+        let name = match self.peek() {
+            Some(LexItem::Variable(name)) => {
+                let name = name.clone();
+                self.current += 1;
+                name
+            }
+            _ if recursive => return Err(self.unexpected(&[Expected::Variable])),
+            _ => return Err(self.unexpected(&[Expected::Rec, Expected::Variable])),
+        };
+
+        // Expect an equals sign '='
+        if let Some(LexItem::BinaryOp(BinaryOperator::Equals)) = self.peek() {
+            self.current += 1;
+        } else {
+            return Err(self.unexpected(&[Expected::Equals]));
+        }
+
+        if recursive {
+            return self.parse_let_rec_expression(start, name);
+        }
+
+        // Parse the bound value
+        let value_expr = self.parse_expression()?;
+
+        // Expect the "in" keyword
+        if let Some(LexItem::In) = self.peek() {
+            self.current += 1;
+        } else {
+            return Err(self.unexpected(&[Expected::In]));
+        }
+
+        // Parse the body, in which the name is bound
+        let body_expr = self.parse_expression()?;
+
+        // Construct the Let expression
+        let let_expr = Expression::Let {
+            name,
+            value: Box::new(value_expr),
+            body: Box::new(body_expr),
+        };
+
+        Ok(self.finish(start, let_expr))
+    }
+
+    /// Parses the rest of a "let rec name =" that began at `start`. The value
+    /// must be a function, since nothing else can refer to itself.
+    fn parse_let_rec_expression(
+        &mut self,
+        start: usize,
+        name: String,
+    ) -> Result<Expression, ParseError> {
+        // Expect the "func" keyword
+        let func_start = self.start();
+        if let Some(LexItem::Func) = self.peek() {
+            self.current += 1;
+        } else {
+            return Err(self.unexpected(&[Expected::Func]));
+        }
+
+        let (param, func_body) = self.parse_func_rest(func_start)?;
+
+        // Expect the "in" keyword
+        if let Some(LexItem::In) = self.peek() {
+            self.current += 1;
+        } else {
+            return Err(self.unexpected(&[Expected::In]));
+        }
+
+        // Parse the body, in which the function is bound
+        let body_expr = self.parse_expression()?;
+
+        // Construct the LetRec expression
+        let let_rec_expr = Expression::LetRec {
+            name,
+            param,
+            func_body: Box::new(func_body),
+            body: Box::new(body_expr),
+        };
+
+        Ok(self.finish(start, let_rec_expr))
+    }
+
+    fn parse_call_expression(&mut self, builtin: Builtin) -> Result<Expression, ParseError> {
+        let start = self.start();
+
+        // Expect the function name
+        if let Some(LexItem::Variable(_)) = self.peek() {
+            self.current += 1;
+        } else {
+            return Err(self.unexpected(&[Expected::Variable]));
+        }
+
+        // Expect an opening parenthesis '('
+        if let Some(LexItem::OpenParen) = self.peek() {
+            self.current += 1;
+        } else {
+            return Err(self.unexpected(&[Expected::OpenParen]));
+        }
+
+        // Parse exactly as many comma-separated arguments as the function takes
+        let mut args = Vec::with_capacity(builtin.arity());
+        for index in 0..builtin.arity() {
+            if index > 0 {
+                if let Some(LexItem::Comma) = self.peek() {
+                    self.current += 1;
+                } else {
+                    return Err(self.unexpected(&[Expected::Comma]));
+                }
+            }
+            args.push(self.parse_expression()?);
+        }
+
+        // Expect a closing parenthesis ')'
+        if let Some(LexItem::CloseParen) = self.peek() {
+            self.current += 1;
+        } else {
+            return Err(self.unexpected(&[Expected::CloseParen]));
+        }
+
+        Ok(self.finish(start, Expression::Call { builtin, args }))
+    }
+}
+
+// Helper function to reorder `postorder`, the spans of `expr` and its descendants
+// in the order the parser completed them, into `preorder` by node id
+fn preorder_spans(
+    expr: &Expression,
+    postorder: &[Span],
+    next: &mut usize,
+    preorder: &mut Vec<Span>,
+) {
+    let id = preorder.len();
+    preorder.push(Span::new(0, 0));
+    for child in expr.children() {
+        preorder_spans(child, postorder, next, preorder);
     }
+    preorder[id] = postorder[*next];
+    *next += 1;
 }
@@ -0,0 +1,179 @@
+// Fractions are all `Int` arithmetic shared by both integer types, see `Int`
+
+use std::cmp::Ordering;
+use std::fmt::{Display, Error};
+
+use crate::evaluator::Arithmetic;
+use crate::expression::BinaryOperator;
+use crate::integer::{self, int, Int};
+
+/// An exact fraction, always in lowest terms with a positive denominator, so
+/// equal fractions are structurally equal.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Rational {
+    numer: Int,
+    denom: Int,
+}
+
+// Fractions are always checked for overflow, since wrapping or saturating the
+// numerator or denominator would give an unrelated fraction
+const CHECKED: Arithmetic = Arithmetic::Checked;
+
+impl Rational {
+    /// The fraction `numer / denom` in lowest terms, or `None` if `denom` is
+    /// zero or the fraction does not fit.
+    pub fn new(numer: Int, denom: Int) -> Option<Rational> {
+        if denom == int(0) {
+            return None;
+        }
+        let divisor = CHECKED.gcd(&numer, &denom)?;
+        let mut numer = CHECKED.apply(BinaryOperator::Divide, &numer, &divisor)?;
+        let mut denom = CHECKED.apply(BinaryOperator::Divide, &denom, &divisor)?;
+        if denom < int(0) {
+            numer = CHECKED.negate(&numer)?;
+            denom = CHECKED.negate(&denom)?;
+        }
+        Some(Rational { numer, denom })
+    }
+
+    pub fn numer(&self) -> &Int {
+        &self.numer
+    }
+
+    pub fn denom(&self) -> &Int {
+        &self.denom
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.denom == int(1)
+    }
+
+    /// The float nearest to the fraction.
+    pub fn to_f64(&self) -> f64 {
+        integer::to_f64(&self.numer) / integer::to_f64(&self.denom)
+    }
+
+    // Negates the fraction, returning None on overflow
+    pub(crate) fn negate(&self) -> Option<Rational> {
+        Some(Rational {
+            numer: CHECKED.negate(&self.numer)?,
+            denom: integer::dup(&self.denom),
+        })
+    }
+
+    // Applies `+`, `-`, `*` or `/`, returning None on overflow or division by zero
+    pub(crate) fn apply(&self, op: BinaryOperator, other: &Rational) -> Option<Rational> {
+        let mul = |a: &Int, b: &Int| CHECKED.apply(BinaryOperator::Multiply, a, b);
+        match op {
+            BinaryOperator::Add | BinaryOperator::Subtract => {
+                let lhs = mul(&self.numer, &other.denom)?;
+                let rhs = mul(&other.numer, &self.denom)?;
+                let numer = CHECKED.apply(op, &lhs, &rhs)?;
+                Rational::new(numer, mul(&self.denom, &other.denom)?)
+            }
+            BinaryOperator::Multiply => Rational::new(
+                mul(&self.numer, &other.numer)?,
+                mul(&self.denom, &other.denom)?,
+            ),
+            BinaryOperator::Divide => Rational::new(
+                mul(&self.numer, &other.denom)?,
+                mul(&self.denom, &other.numer)?,
+            ),
+            _ => None,
+        }
+    }
+
+    // Orders two fractions, returning None on overflow. Denominators are
+    // positive, so cross multiplying keeps the order.
+    pub(crate) fn compare(&self, other: &Rational) -> Option<Ordering> {
+        let lhs = CHECKED.apply(BinaryOperator::Multiply, &self.numer, &other.denom)?;
+        let rhs = CHECKED.apply(BinaryOperator::Multiply, &other.numer, &self.denom)?;
+        Some(lhs.cmp(&rhs))
+    }
+
+    /// Writes the fraction as a decimal with `places` digits after the point,
+    /// rounding halves away from zero.
+    // The `Int` operators take references so as not to move a `BigInt`
+    #[cfg_attr(not(feature = "bigint"), allow(clippy::op_ref))]
+    pub fn to_decimal(&self, places: usize) -> String {
+        // Long division on the magnitude, one digit at a time. The remainder
+        // is below the denominator, so none of the steps overflow.
+        let whole = &self.numer / &self.denom;
+        let mut remainder = &self.numer % &self.denom;
+        if remainder < int(0) {
+            remainder = int(0) - &remainder;
+        }
+        let mut digits: Vec<u8> = whole
+            .to_string()
+            .trim_start_matches('-')
+            .bytes()
+            .map(|digit| digit - b'0')
+            .collect();
+        for _ in 0..places {
+            // Multiply the remainder by ten by adding it ten times, carrying
+            // whenever the sum reaches the denominator
+            let mut digit = 0;
+            let mut next = int(0);
+            for _ in 0..10 {
+                let room = &self.denom - &remainder;
+                if next >= room {
+                    next = &next - &room;
+                    digit += 1;
+                } else {
+                    next = &next + &remainder;
+                }
+            }
+            digits.push(digit);
+            remainder = next;
+        }
+        // Round up when the remainder is at least half the denominator
+        if remainder >= &self.denom - &remainder {
+            let mut carry = true;
+            for digit in digits.iter_mut().rev() {
+                if *digit == 9 {
+                    *digit = 0;
+                } else {
+                    *digit += 1;
+                    carry = false;
+                    break;
+                }
+            }
+            if carry {
+                digits.insert(0, 1);
+            }
+        }
+
+        let mut decimal = String::new();
+        if self.numer < int(0) && digits.iter().any(|&digit| digit != 0) {
+            decimal.push('-');
+        }
+        let point = digits.len() - places;
+        for (index, digit) in digits.into_iter().enumerate() {
+            if index == point {
+                decimal.push('.');
+            }
+            decimal.push(char::from(b'0' + digit));
+        }
+        decimal
+    }
+}
+
+impl From<Int> for Rational {
+    fn from(value: Int) -> Self {
+        Rational {
+            numer: value,
+            denom: int(1),
+        }
+    }
+}
+
+// A precision, as in "{:.2}", writes the fraction as a decimal with that many places
+impl Display for Rational {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match f.precision() {
+            Some(places) => write!(f, "{}", self.to_decimal(places)),
+            None if self.is_integer() => write!(f, "{}", self.numer),
+            None => write!(f, "{}/{}", self.numer, self.denom),
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt::{Display, Error};
+
+/// A byte range `start..end` into the source text.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A 1-based line and column in the source text. Columns count characters, not bytes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl Position {
+    /// Finds the line and column of the byte `offset` in `source`.
+    pub fn locate(source: &str, offset: usize) -> Self {
+        let mut line = 1;
+        let mut column = 1;
+        for (index, c) in source.char_indices() {
+            if index >= offset {
+                break;
+            }
+            if c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+        Position { line, column }
+    }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Identifies a node of an [`Expression`](crate::expression::Expression) by its
+/// index in preorder: the root is 0, and every node comes before its children,
+/// which are numbered left to right as they appear in the source.
+pub type NodeId = usize;
+
+/// The source spans of the nodes of an expression, indexed by [`NodeId`].
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Spans(Vec<Span>);
+
+impl Spans {
+    pub fn new(spans: Vec<Span>) -> Self {
+        Spans(spans)
+    }
+
+    /// The span of node `id`, or `None` if there is no such node.
+    pub fn get(&self, id: NodeId) -> Option<Span> {
+        self.0.get(id).copied()
+    }
+
+    /// The span of the whole expression.
+    pub fn root(&self) -> Option<Span> {
+        self.get(0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Span> + '_ {
+        self.0.iter().copied()
+    }
+}
@@ -1,10 +1,57 @@
+// Helpers shared by the test modules below
+#[cfg(test)]
+mod helpers {
+    use crate::error::EvalError;
+    use crate::evaluator::{Division, Floats};
+    use crate::expression::Expression;
+    use crate::parser::{Parser, Syntax};
+    use crate::value::Value;
+
+    pub(super) fn parse_prefix(program: &str) -> Expression {
+        Parser::new(program).parse().unwrap()
+    }
+
+    pub(super) fn eval_prefix(program: &str) -> Result<Value, EvalError> {
+        parse_prefix(program).eval()
+    }
+
+    pub(super) fn parse_infix(program: &str) -> Expression {
+        Parser::new(program)
+            .with_syntax(Syntax::Infix)
+            .parse()
+            .unwrap()
+    }
+
+    pub(super) fn eval_infix(program: &str) -> Result<Value, EvalError> {
+        parse_infix(program).eval()
+    }
+
+    // The evaluator settings eval_infix_with runs a program under
+    #[derive(Clone, Copy)]
+    pub(super) enum Mode {
+        // Division::Exact
+        Exact,
+        // Floats::Decimal
+        Decimal,
+    }
+
+    pub(super) fn eval_infix_with(program: &str, mode: Mode) -> Result<Value, EvalError> {
+        let expr = parse_infix(program);
+        match mode {
+            Mode::Exact => expr.eval_with_division(Division::Exact),
+            Mode::Decimal => expr.eval_with_floats(Floats::Decimal),
+        }
+    }
+}
+
 #[cfg(test)]
 mod display_tests {
     use crate::expression::{BinaryOperator, Expression, UnaryOperator};
+    use crate::integer::int;
 
     #[test]
     fn test_display_integer() {
-        let expr = Expression::Integer(42);
+        let expr = Expression::Integer(int(42));
         assert_eq!(format!("{}", expr), "42");
     }
 
@@ -27,8 +74,8 @@ mod display_tests {
     fn test_display_binary_op() {
         let expr = Expression::BinaryOp {
             op: BinaryOperator::Add,
-            lhs: Box::new(Expression::Integer(3)),
-            rhs: Box::new(Expression::Integer(4)),
+            lhs: Box::new(Expression::Integer(int(3))),
+            rhs: Box::new(Expression::Integer(int(4))),
         };
         assert_eq!(format!("{}", expr), "3 + 4");
     }
@@ -49,7 +96,7 @@ mod display_tests {
             body: Box::new(Expression::BinaryOp {
                 op: BinaryOperator::Multiply,
                 lhs: Box::new(Expression::Variable("x".to_string())),
-                rhs: Box::new(Expression::Integer(2)),
+                rhs: Box::new(Expression::Integer(int(2))),
             }),
         };
         assert_eq!(format!("{}", expr), "func x => x * 2");
@@ -59,8 +106,8 @@ mod display_tests {
     fn test_display_if() {
         let expr = Expression::If {
             condition: Box::new(Expression::Boolean(true)),
-            then_expr: Box::new(Expression::Integer(42)),
-            else_expr: Box::new(Expression::Integer(0)),
+            then_expr: Box::new(Expression::Integer(int(42))),
+            else_expr: Box::new(Expression::Integer(int(0))),
         };
         assert_eq!(format!("{}", expr), "if T then 42 else 0");
     }
@@ -69,22 +116,23 @@ mod display_tests {
     fn test_display_apply() {
         let expr = Expression::Apply {
             func_expr: Box::new(Expression::Variable("f".to_string())),
-            arg_expr: Box::new(Expression::Integer(10)),
+            arg_expr: Box::new(Expression::Integer(int(10))),
         };
-        assert_eq!(format!("{}", expr), "f (10)");
+        assert_eq!(format!("{}", expr), "apply(f, 10)");
     }
 }
 
 #[cfg(test)]
 mod lexing_tests {
     use crate::expression::BinaryOperator;
+    use crate::integer::int;
     use crate::parser::{lex, LexItem};
 
     #[test]
     fn lex_integer() {
         let input = "123";
         let result = lex(input);
-        assert_eq!(result, Ok(vec![LexItem::Integer(123)]));
+        assert_eq!(result, Ok(vec![LexItem::Integer(int(123))]));
     }
 
     #[test]
@@ -124,9 +172,9 @@ mod lexing_tests {
             Ok(vec![
                 LexItem::BinaryOp(BinaryOperator::Add),
                 LexItem::OpenParen,
-                LexItem::Integer(1),
+                LexItem::Integer(int(1)),
                 LexItem::Comma,
-                LexItem::Integer(1),
+                LexItem::Integer(int(1)),
                 LexItem::CloseParen
             ])
         );
@@ -141,16 +189,18 @@ mod lexing_tests {
             Ok(vec![
                 LexItem::BinaryOp(BinaryOperator::Subtract),
                 LexItem::OpenParen,
-                LexItem::Integer(1),
+                LexItem::Integer(int(1)),
                 LexItem::Comma,
-                LexItem::Integer(1),
+                LexItem::Integer(int(1)),
                 LexItem::CloseParen
             ])
         );
     }
 }
 
+// The programs are borrowed as they were in the original tests
 #[cfg(test)]
+#[allow(clippy::needless_borrow)]
 mod arith_tests {
     use crate::parser::Parser;
 
@@ -196,7 +246,7 @@ mod arith_tests {
         let result = prog.parse();
         assert!(result.is_ok());
         let e = result.unwrap();
-        assert_eq!("1 + 1 + 1", format!("{}", e));
+        assert_eq!("1 + (1 + 1)", format!("{}", e));
     }
 
     #[test]
@@ -286,7 +336,7 @@ mod arith_tests {
         let result = prog.parse();
         assert!(result.is_ok());
         let e = result.unwrap();
-        assert_eq!("func x => x (1)", format!("{}", e));
+        assert_eq!("apply(func x => x, 1)", format!("{}", e));
     }
 
     #[test]
@@ -299,7 +349,9 @@ mod arith_tests {
     }
 }
 
+// The programs are borrowed as they were in the original tests
 #[cfg(test)]
+#[allow(clippy::needless_borrow)]
 mod nested_tests {
 
     use crate::parser::Parser;
@@ -310,7 +362,7 @@ mod nested_tests {
         let result = prog.parse();
         assert!(result.is_ok());
         let e = result.unwrap();
-        assert_eq!("1 + 2 - 3", format!("{}", e));
+        assert_eq!("1 + (2 - 3)", format!("{}", e));
     }
 
     #[test]
@@ -319,7 +371,7 @@ mod nested_tests {
         let result = prog.parse();
         assert!(result.is_ok());
         let e = result.unwrap();
-        assert_eq!("func x => x - 2 (5)", format!("{}", e));
+        assert_eq!("apply(func x => x - 2, 5)", format!("{}", e));
     }
 
     #[test]
@@ -341,7 +393,7 @@ mod nested_tests {
         assert!(result.is_ok());
         let e = result.unwrap();
         assert_eq!(
-            "func x => if x < 10 then 10 - x else x + 10 (5)",
+            "apply(func x => if x < 10 then 10 - x else x + 10, 5)",
             format!("{}", e)
         );
     }
@@ -362,28 +414,37 @@ mod nested_tests {
 
 #[cfg(test)]
 mod eval_tests {
+    use crate::integer::int;
 
+    use crate::error::EvalError;
     use crate::expression::{BinaryOperator, Expression, UnaryOperator};
+    use crate::value::Value;
 
     #[test]
     fn eval_integer() {
-        let expr = Expression::Integer(42);
+        let expr = Expression::Integer(int(42));
         let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Integer(42)));
+        assert_eq!(result, Ok(Value::Int(int(42))));
     }
 
     #[test]
     fn eval_variable() {
         let expr = Expression::Variable("x".to_string());
         let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Variable("x".to_string())));
+        assert_eq!(
+            result,
+            Err(EvalError::UnboundVariable {
+                name: "x".to_string(),
+                span: None,
+            })
+        );
     }
 
     #[test]
     fn eval_boolean() {
         let expr = Expression::Boolean(true);
         let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Boolean(true)));
+        assert_eq!(result, Ok(Value::Bool(true)));
     }
 
     #[test]
@@ -393,7 +454,7 @@ mod eval_tests {
             child: Box::new(Expression::Boolean(true)),
         };
         let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Boolean(false)));
+        assert_eq!(result, Ok(Value::Bool(false)));
     }
 
     #[test]
@@ -403,117 +464,119 @@ mod eval_tests {
             child: Box::new(Expression::Boolean(false)),
         };
         let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Boolean(true)));
+        assert_eq!(result, Ok(Value::Bool(true)));
     }
 
     #[test]
     fn eval_addition() {
         let expr = Expression::BinaryOp {
             op: BinaryOperator::Add,
-            lhs: Box::new(Expression::Integer(2)),
-            rhs: Box::new(Expression::Integer(3)),
+            lhs: Box::new(Expression::Integer(int(2))),
+            rhs: Box::new(Expression::Integer(int(3))),
         };
         let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Integer(5)));
+        assert_eq!(result, Ok(Value::Int(int(5))));
     }
 
     #[test]
     fn eval_subtraction() {
         let expr = Expression::BinaryOp {
             op: BinaryOperator::Subtract,
-            lhs: Box::new(Expression::Integer(8)),
-            rhs: Box::new(Expression::Integer(3)),
+            lhs: Box::new(Expression::Integer(int(8))),
+            rhs: Box::new(Expression::Integer(int(3))),
         };
         let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Integer(5)));
+        assert_eq!(result, Ok(Value::Int(int(5))));
     }
 
     #[test]
     fn eval_multiplication() {
         let expr = Expression::BinaryOp {
             op: BinaryOperator::Multiply,
-            lhs: Box::new(Expression::Integer(2)),
-            rhs: Box::new(Expression::Integer(3)),
+            lhs: Box::new(Expression::Integer(int(2))),
+            rhs: Box::new(Expression::Integer(int(3))),
         };
         let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Integer(6)));
+        assert_eq!(result, Ok(Value::Int(int(6))));
     }
 
     #[test]
     fn eval_division() {
         let expr = Expression::BinaryOp {
             op: BinaryOperator::Divide,
-            lhs: Box::new(Expression::Integer(10)),
-            rhs: Box::new(Expression::Integer(2)),
+            lhs: Box::new(Expression::Integer(int(10))),
+            rhs: Box::new(Expression::Integer(int(2))),
         };
         let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Integer(5)));
+        assert_eq!(result, Ok(Value::Int(int(5))));
     }
 
     #[test]
     fn eval_less_than_true() {
         let expr = Expression::BinaryOp {
             op: BinaryOperator::LessThan,
-            lhs: Box::new(Expression::Integer(3)),
-            rhs: Box::new(Expression::Integer(5)),
+            lhs: Box::new(Expression::Integer(int(3))),
+            rhs: Box::new(Expression::Integer(int(5))),
         };
         let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Boolean(true)));
+        assert_eq!(result, Ok(Value::Bool(true)));
     }
 
     #[test]
     fn eval_less_than_false() {
         let expr = Expression::BinaryOp {
             op: BinaryOperator::LessThan,
-            lhs: Box::new(Expression::Integer(8)),
-            rhs: Box::new(Expression::Integer(5)),
+            lhs: Box::new(Expression::Integer(int(8))),
+            rhs: Box::new(Expression::Integer(int(5))),
         };
         let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Boolean(false)));
+        assert_eq!(result, Ok(Value::Bool(false)));
     }
 
     #[test]
     fn eval_equals_true() {
         let expr = Expression::BinaryOp {
             op: BinaryOperator::Equals,
-            lhs: Box::new(Expression::Integer(4)),
-            rhs: Box::new(Expression::Integer(4)),
+            lhs: Box::new(Expression::Integer(int(4))),
+            rhs: Box::new(Expression::Integer(int(4))),
         };
         let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Boolean(true)));
+        assert_eq!(result, Ok(Value::Bool(true)));
     }
 
     #[test]
     fn eval_equals_false() {
         let expr = Expression::BinaryOp {
             op: BinaryOperator::Equals,
-            lhs: Box::new(Expression::Integer(2)),
-            rhs: Box::new(Expression::Integer(5)),
+            lhs: Box::new(Expression::Integer(int(2))),
+            rhs: Box::new(Expression::Integer(int(5))),
         };
         let result = expr.eval();
-        assert_eq!(result, Ok(Expression::Boolean(false)));
+        assert_eq!(result, Ok(Value::Bool(false)));
     }
 }
 
 #[cfg(test)]
 mod nested_eval_tests {
     use crate::expression::{BinaryOperator, Expression};
+    use crate::integer::int;
+    use crate::value::Value;
 
     #[test]
     fn eval_nested_addition() {
         // Test: +(1, +(2, 3))
         let expression = Expression::BinaryOp {
             op: BinaryOperator::Add,
-            lhs: Box::new(Expression::Integer(1)),
+            lhs: Box::new(Expression::Integer(int(1))),
             rhs: Box::new(Expression::BinaryOp {
                 op: BinaryOperator::Add,
-                lhs: Box::new(Expression::Integer(2)),
-                rhs: Box::new(Expression::Integer(3)),
+                lhs: Box::new(Expression::Integer(int(2))),
+                rhs: Box::new(Expression::Integer(int(3))),
             }),
         };
         let result = expression.eval();
         assert!(result.is_ok());
-        assert_eq!(Expression::Integer(6), result.unwrap());
+        assert_eq!(Value::Int(int(6)), result.unwrap());
     }
 
     #[test]
@@ -521,16 +584,16 @@ mod nested_eval_tests {
         // Test: -(10, -(5, 3))
         let expression = Expression::BinaryOp {
             op: BinaryOperator::Subtract,
-            lhs: Box::new(Expression::Integer(10)),
+            lhs: Box::new(Expression::Integer(int(10))),
             rhs: Box::new(Expression::BinaryOp {
                 op: BinaryOperator::Subtract,
-                lhs: Box::new(Expression::Integer(5)),
-                rhs: Box::new(Expression::Integer(3)),
+                lhs: Box::new(Expression::Integer(int(5))),
+                rhs: Box::new(Expression::Integer(int(3))),
             }),
         };
         let result = expression.eval();
         assert!(result.is_ok());
-        assert_eq!(Expression::Integer(8), result.unwrap());
+        assert_eq!(Value::Int(int(8)), result.unwrap());
     }
 
     #[test]
@@ -538,16 +601,16 @@ mod nested_eval_tests {
         // Test: *(3, *(2, 4))
         let expression = Expression::BinaryOp {
             op: BinaryOperator::Multiply,
-            lhs: Box::new(Expression::Integer(3)),
+            lhs: Box::new(Expression::Integer(int(3))),
             rhs: Box::new(Expression::BinaryOp {
                 op: BinaryOperator::Multiply,
-                lhs: Box::new(Expression::Integer(2)),
-                rhs: Box::new(Expression::Integer(4)),
+                lhs: Box::new(Expression::Integer(int(2))),
+                rhs: Box::new(Expression::Integer(int(4))),
             }),
         };
         let result = expression.eval();
         assert!(result.is_ok());
-        assert_eq!(Expression::Integer(24), result.unwrap());
+        assert_eq!(Value::Int(int(24)), result.unwrap());
     }
 
     #[test]
@@ -555,16 +618,16 @@ mod nested_eval_tests {
         // Test: /(15, /(6, 2))
         let expression = Expression::BinaryOp {
             op: BinaryOperator::Divide,
-            lhs: Box::new(Expression::Integer(15)),
+            lhs: Box::new(Expression::Integer(int(15))),
             rhs: Box::new(Expression::BinaryOp {
                 op: BinaryOperator::Divide,
-                lhs: Box::new(Expression::Integer(6)),
-                rhs: Box::new(Expression::Integer(2)),
+                lhs: Box::new(Expression::Integer(int(6))),
+                rhs: Box::new(Expression::Integer(int(2))),
             }),
         };
         let result = expression.eval();
         assert!(result.is_ok());
-        assert_eq!(Expression::Integer(5), result.unwrap());
+        assert_eq!(Value::Int(int(5)), result.unwrap());
     }
 
     #[test]
@@ -581,7 +644,7 @@ mod nested_eval_tests {
         };
         let result = expression.eval();
         assert!(result.is_ok());
-        assert_eq!(Expression::Boolean(false), result.unwrap());
+        assert_eq!(Value::Bool(false), result.unwrap());
     }
 
     #[test]
@@ -598,90 +661,92 @@ mod nested_eval_tests {
         };
         let result = expression.eval();
         assert!(result.is_ok());
-        assert_eq!(Expression::Boolean(true), result.unwrap());
+        assert_eq!(Value::Bool(true), result.unwrap());
     }
 }
 
 #[cfg(test)]
 mod apply_tests {
-    use crate::expression::Expression;
+    use crate::integer::int;
     use crate::parser::Parser;
+    use crate::value::Value;
 
     #[test]
     fn eval_apply_addition() {
         let mut prog = Parser::new("apply(func x => +(x, 1), 2)");
         let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Integer(3)));
+        assert_eq!(result, Ok(Value::Int(int(3))));
     }
 
     #[test]
     fn eval_apply_subtraction() {
         let mut prog = Parser::new("apply(func x => -(x, 2), 5)");
         let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Integer(3)));
+        assert_eq!(result, Ok(Value::Int(int(3))));
     }
 
     #[test]
     fn eval_apply_multiplication() {
         let mut prog = Parser::new("apply(func x => *(x, 3), 4)");
         let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Integer(12)));
+        assert_eq!(result, Ok(Value::Int(int(12))));
     }
 
     #[test]
     fn eval_apply_division() {
         let mut prog = Parser::new("apply(func x => /(x, 2), 10)");
         let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Integer(5)));
+        assert_eq!(result, Ok(Value::Int(int(5))));
     }
 
     #[test]
     fn eval_apply_equals() {
         let mut prog = Parser::new("apply(func x => =(x, 3), 3)");
         let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Boolean(true)));
+        assert_eq!(result, Ok(Value::Bool(true)));
     }
 
     #[test]
     fn eval_apply_less_than() {
         let mut prog = Parser::new("apply(func x => <(x, 5), 3)");
         let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Boolean(true)));
+        assert_eq!(result, Ok(Value::Bool(true)));
     }
 
     #[test]
     fn eval_apply_and() {
         let mut prog = Parser::new("apply(func x => &(x, T), F)");
         let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Boolean(false)));
+        assert_eq!(result, Ok(Value::Bool(false)));
     }
 
     #[test]
     fn eval_apply_or() {
         let mut prog = Parser::new("apply(func x => |(x, T), F)");
         let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Boolean(true)));
+        assert_eq!(result, Ok(Value::Bool(true)));
     }
 
     #[test]
     fn eval_apply_not() {
         let mut prog = Parser::new("apply(func x => !x, T)");
         let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Boolean(false)));
+        assert_eq!(result, Ok(Value::Bool(false)));
     }
 }
 
 #[cfg(test)]
 mod if_expression_tests {
-    use crate::expression::Expression;
+    use crate::integer::int;
     use crate::parser::Parser;
+    use crate::value::Value;
 
     #[test]
     fn eval_if_true() {
         // if T then 2 else 3
         let mut prog = Parser::new("if T then 2 else 3");
         let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Integer(2)));
+        assert_eq!(result, Ok(Value::Int(int(2))));
     }
 
     #[test]
@@ -689,7 +754,7 @@ mod if_expression_tests {
         // if F then 2 else 3
         let mut prog = Parser::new("if F then 2 else 3");
         let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Integer(3)));
+        assert_eq!(result, Ok(Value::Int(int(3))));
     }
 
     #[test]
@@ -697,6 +762,3510 @@ mod if_expression_tests {
         // if <(2, 3) then if T then 4 else 5 else 6
         let mut prog = Parser::new("if <(2, 3) then if T then 4 else 5 else 6");
         let result = prog.parse().unwrap().eval();
-        assert_eq!(result, Ok(Expression::Integer(4)));
+        assert_eq!(result, Ok(Value::Int(int(4))));
+    }
+}
+#[cfg(test)]
+mod api_tests {
+    use crate::integer::int;
+    use crate::{parse_and_eval, Value};
+
+    #[test]
+    fn parse_and_eval_expression() {
+        let result = parse_and_eval("apply(func x => *(x, 3), 4)");
+        assert_eq!(result, Ok(Value::Int(int(12))));
+    }
+
+    #[test]
+    fn parse_and_eval_reports_parse_error() {
+        let result = parse_and_eval("+(1");
+        assert!(result.is_err());
+    }
+}
+
+#[cfg(test)]
+mod parse_error_tests {
+    use crate::error::{Expected, ParseError};
+    use crate::expression::BinaryOperator;
+    use crate::integer::int;
+    use crate::parser::{LexItem, Parser};
+    use crate::span::{Position, Span};
+
+    #[test]
+    fn missing_comma_points_at_token() {
+        let mut prog = Parser::new("+(1 2)");
+        let result = prog.parse();
+        assert_eq!(
+            result,
+            Err(ParseError::UnexpectedToken {
+                found: LexItem::Integer(int(2)),
+                span: Span::new(4, 5),
+                position: Position { line: 1, column: 5 },
+                expected: vec![Expected::Comma],
+            })
+        );
+    }
+
+    #[test]
+    fn missing_paren_after_operator() {
+        let mut prog = Parser::new("*1");
+        let error = prog.parse().unwrap_err();
+        assert_eq!(error.span(), Span::new(1, 2));
+        assert_eq!(error.expected(), &[Expected::OpenParen]);
+    }
+
+    #[test]
+    fn unexpected_end_of_input() {
+        let mut prog = Parser::new("if T then 1");
+        let result = prog.parse();
+        assert_eq!(
+            result,
+            Err(ParseError::UnexpectedEof {
+                offset: 11,
+                position: Position {
+                    line: 1,
+                    column: 12
+                },
+                expected: vec![Expected::Else],
+            })
+        );
+    }
+
+    #[test]
+    fn unexpected_token_in_expression_position() {
+        let mut prog = Parser::new("apply(, 1)");
+        let result = prog.parse();
+        assert_eq!(
+            result,
+            Err(ParseError::UnexpectedToken {
+                found: LexItem::Comma,
+                span: Span::new(6, 7),
+                position: Position { line: 1, column: 7 },
+                expected: vec![Expected::Expression],
+            })
+        );
+    }
+
+    #[test]
+    fn display_parse_error() {
+        let mut prog = Parser::new("func 1 => 2");
+        let error = prog.parse().unwrap_err();
+        assert_eq!(
+            format!("{}", error),
+            "1:6: expected variable name, found '1'"
+        );
+
+        let mut prog = Parser::new("=(1");
+        let error = prog.parse().unwrap_err();
+        assert_eq!(
+            format!("{}", error),
+            "1:4: expected ',', found end of input"
+        );
+    }
+
+    #[test]
+    fn multi_character_token_span() {
+        let mut prog = Parser::new("func x => => 1");
+        let error = prog.parse().unwrap_err();
+        assert_eq!(error.span(), Span::new(10, 12));
+
+        let mut prog = Parser::new("+(123 =)");
+        let error = prog.parse().unwrap_err();
+        assert_eq!(
+            error,
+            ParseError::UnexpectedToken {
+                found: LexItem::BinaryOp(BinaryOperator::Equals),
+                span: Span::new(6, 7),
+                position: Position { line: 1, column: 7 },
+                expected: vec![Expected::Comma],
+            }
+        );
+    }
+}
+
+#[cfg(test)]
+mod lex_error_tests {
+    use crate::error::{LexError, ParseError};
+    use crate::parser::{lex, Parser};
+    use crate::span::{Position, Span};
+
+    #[test]
+    fn lex_unexpected_character() {
+        let result = lex("+(1, $)");
+        assert_eq!(
+            result,
+            Err(LexError::UnexpectedCharacter {
+                found: '$',
+                span: Span::new(5, 6),
+                position: Position { line: 1, column: 6 },
+            })
+        );
+    }
+
+    #[test]
+    fn parse_reports_lex_error() {
+        let mut prog = Parser::new("1 $ 2");
+        let result = prog.parse();
+        assert_eq!(
+            result,
+            Err(ParseError::Lex(LexError::UnexpectedCharacter {
+                found: '$',
+                span: Span::new(2, 3),
+                position: Position { line: 1, column: 3 },
+            }))
+        );
+    }
+
+    #[test]
+    fn try_new_fails_on_lex_error() {
+        let result = Parser::try_new("+(1, €)");
+        let error = result.err().unwrap();
+        assert_eq!(error.span(), Span::new(5, 8));
+        assert_eq!(format!("{}", error), "1:6: unexpected character '€'");
+    }
+
+    #[test]
+    fn try_new_accepts_valid_program() {
+        let mut prog = Parser::try_new("+(1, 2)").unwrap();
+        assert!(prog.parse().is_ok());
+    }
+}
+
+#[cfg(test)]
+mod trailing_input_tests {
+    use crate::error::ParseError;
+    use crate::expression::Expression;
+    use crate::integer::int;
+    use crate::parser::{LexItem, Parser};
+    use crate::span::{Position, Span};
+
+    #[test]
+    fn parse_rejects_trailing_tokens() {
+        let mut prog = Parser::new("+(1,2) 3 )");
+        let result = prog.parse();
+        assert_eq!(
+            result,
+            Err(ParseError::TrailingInput {
+                found: LexItem::Integer(int(3)),
+                span: Span::new(7, 8),
+                position: Position { line: 1, column: 8 },
+            })
+        );
+    }
+
+    #[test]
+    fn parse_rejects_trailing_paren() {
+        let mut prog = Parser::new("T)");
+        let error = prog.parse().unwrap_err();
+        assert_eq!(
+            format!("{}", error),
+            "1:2: unexpected ')' after end of expression"
+        );
+    }
+
+    #[test]
+    fn parse_prefix_returns_remainder() {
+        let mut prog = Parser::new("+(1,2) 3 )");
+        let (e, rest) = prog.parse_prefix().unwrap();
+        assert_eq!("1 + 2", format!("{}", e));
+        assert_eq!(rest, "3 )");
+    }
+
+    #[test]
+    fn parse_prefix_streams_expressions() {
+        let mut prog = Parser::new("1 T  x");
+        assert_eq!(
+            prog.parse_prefix(),
+            Ok((Expression::Integer(int(1)), "T  x"))
+        );
+        assert_eq!(prog.parse_prefix(), Ok((Expression::Boolean(true), "x")));
+        assert_eq!(
+            prog.parse_prefix(),
+            Ok((Expression::Variable("x".to_string()), ""))
+        );
+    }
+}
+
+#[cfg(test)]
+mod eval_error_tests {
+    use crate::error::{EvalError, Type};
+    use crate::evaluator::{Evaluator, Unbound};
+    use crate::expression::{BinaryOperator, Expression, Operator, UnaryOperator};
+    use crate::integer::int;
+    use crate::parser::Parser;
+
+    #[test]
+    fn eval_add_type_mismatch() {
+        let mut prog = Parser::new("+(1, &(T, F))");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result,
+            Err(EvalError::TypeMismatch {
+                op: Operator::Binary(BinaryOperator::Add),
+                expected: Type::Integer,
+                found: Type::Boolean,
+                expr: Expression::BinaryOp {
+                    op: BinaryOperator::And,
+                    lhs: Box::new(Expression::Boolean(true)),
+                    rhs: Box::new(Expression::Boolean(false)),
+                },
+                span: None,
+            })
+        );
+    }
+
+    #[test]
+    fn eval_not_type_mismatch() {
+        let mut prog = Parser::new("!5");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result,
+            Err(EvalError::TypeMismatch {
+                op: Operator::Unary(UnaryOperator::Not),
+                expected: Type::Boolean,
+                found: Type::Integer,
+                expr: Expression::Integer(int(5)),
+                span: None,
+            })
+        );
+    }
+
+    #[test]
+    fn eval_or_symbolic_variable() {
+        let expr = Parser::new("|(T, x)").parse().unwrap();
+        let result = Evaluator::new().with_unbound(Unbound::Symbolic).eval(&expr);
+        assert!(matches!(
+            result,
+            Err(EvalError::TypeMismatch {
+                expected: Type::Boolean,
+                found: Type::Variable,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn eval_apply_not_a_function() {
+        let mut prog = Parser::new("apply(3, 4)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(
+            result,
+            Err(EvalError::NotAFunction {
+                found: Type::Integer,
+                expr: Expression::Integer(int(3)),
+                span: None,
+            })
+        );
+    }
+
+    #[test]
+    fn eval_if_non_boolean_condition() {
+        let mut prog = Parser::new("if +(1, 1) then 2 else 3");
+        let result = prog.parse().unwrap().eval();
+        assert!(matches!(
+            result,
+            Err(EvalError::NonBooleanCondition {
+                found: Type::Integer,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn display_eval_error() {
+        let mut prog = Parser::new("*(T, 2)");
+        let error = prog.parse().unwrap().eval().unwrap_err();
+        assert_eq!(
+            format!("{}", error),
+            "'*' expected integer operand, found boolean in 'T'"
+        );
+    }
+}
+
+#[cfg(test)]
+mod arithmetic_tests {
+    use super::helpers::parse_prefix;
+    use crate::error::EvalError;
+    use crate::evaluator::Arithmetic;
+    #[cfg(not(feature = "bigint"))]
+    use crate::expression::{BinaryOperator, Operator};
+    #[cfg(not(feature = "bigint"))]
+    use crate::integer::int;
+    #[cfg(not(feature = "bigint"))]
+    use crate::value::Value;
+
+    #[test]
+    fn eval_division_by_zero() {
+        let expr = parse_prefix("/(1, 0)");
+        let result = expr.eval();
+        assert_eq!(result, Err(EvalError::DivisionByZero { expr, span: None }));
+    }
+
+    #[test]
+    fn eval_division_by_zero_in_every_mode() {
+        let expr = parse_prefix("/(1, 0)");
+        for arithmetic in [Arithmetic::Wrapping, Arithmetic::Saturating] {
+            let result = expr.eval_with_arithmetic(arithmetic);
+            assert!(matches!(result, Err(EvalError::DivisionByZero { .. })));
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "bigint"))]
+    fn eval_add_overflow() {
+        let expr = parse_prefix("+(9223372036854775807, 1)");
+        let result = expr.eval();
+        assert_eq!(
+            result,
+            Err(EvalError::Overflow {
+                op: Operator::Binary(BinaryOperator::Add),
+                expr,
+                span: None,
+            })
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "bigint"))]
+    fn eval_checked_overflow() {
+        let cases = [
+            parse_prefix("-(-9223372036854775808, 1)"),
+            parse_prefix("*(9223372036854775807, 2)"),
+            parse_prefix("/(-9223372036854775808, -1)"),
+        ];
+        for expr in cases {
+            assert!(matches!(expr.eval(), Err(EvalError::Overflow { .. })));
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "bigint"))]
+    fn eval_wrapping() {
+        let expr = parse_prefix("+(9223372036854775807, 1)");
+        let result = expr.eval_with_arithmetic(Arithmetic::Wrapping);
+        assert_eq!(result, Ok(Value::Int(int(i64::MIN))));
+
+        let expr = parse_prefix("*(9223372036854775807, 2)");
+        let result = expr.eval_with_arithmetic(Arithmetic::Wrapping);
+        assert_eq!(result, Ok(Value::Int(int(-2))));
+    }
+
+    #[test]
+    #[cfg(not(feature = "bigint"))]
+    fn eval_saturating() {
+        let expr = parse_prefix("+(9223372036854775807, 1)");
+        let result = expr.eval_with_arithmetic(Arithmetic::Saturating);
+        assert_eq!(result, Ok(Value::Int(int(i64::MAX))));
+
+        let expr = parse_prefix("-(-9223372036854775808, 1)");
+        let result = expr.eval_with_arithmetic(Arithmetic::Saturating);
+        assert_eq!(result, Ok(Value::Int(int(i64::MIN))));
+    }
+
+    #[test]
+    #[cfg(not(feature = "bigint"))]
+    fn eval_mode_applies_inside_functions() {
+        let expr = parse_prefix("apply(func x => *(x, x), 9999999999)");
+        assert!(matches!(expr.eval(), Err(EvalError::Overflow { .. })));
+        assert_eq!(
+            expr.eval_with_arithmetic(Arithmetic::Saturating),
+            Ok(Value::Int(int(i64::MAX)))
+        );
+    }
+}
+
+#[cfg(test)]
+mod substitution_tests {
+    use crate::integer::int;
+    use std::collections::HashSet;
+
+    use crate::error::{EvalError, Type};
+    use crate::evaluator::{Evaluator, Unbound};
+    use crate::expression::{BinaryOperator, Expression, Operator};
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    #[test]
+    fn eval_apply_substitutes_into_if() {
+        let mut prog = Parser::new("apply(func x => if =(x, 1) then x else 0, 1)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(int(1))));
+    }
+
+    #[test]
+    fn eval_apply_substitutes_into_apply() {
+        let mut prog = Parser::new("apply(func f => apply(f, 4), func y => *(y, y))");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(int(16))));
+    }
+
+    #[test]
+    fn eval_nested_functions() {
+        let mut prog = Parser::new("apply(apply(func x => func y => -(x, y), 10), 3)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(int(7))));
+    }
+
+    #[test]
+    fn eval_inner_function_shadows_param() {
+        let mut prog = Parser::new("apply(apply(func x => func x => x, 1), 2)");
+        let result = prog.parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(int(2))));
+    }
+
+    #[test]
+    fn eval_substitution_avoids_capture() {
+        // The free `y` passed in must not be captured by the inner `func y`
+        let symbolic = Evaluator::new().with_unbound(Unbound::Symbolic);
+        let expr = Parser::new("apply(func x => func y => +(x, y), y)")
+            .parse()
+            .unwrap();
+        let result = symbolic.eval(&expr).unwrap();
+        assert_eq!("func y1 => y + y1", format!("{}", result));
+
+        let expr = Parser::new("apply(apply(func x => func y => +(x, y), y), 1)")
+            .parse()
+            .unwrap();
+        let result = symbolic.eval(&expr);
+        assert_eq!(
+            result,
+            Err(EvalError::TypeMismatch {
+                op: Operator::Binary(BinaryOperator::Add),
+                expected: Type::Integer,
+                found: Type::Variable,
+                expr: Expression::Variable("x".to_string()),
+                span: None,
+            })
+        );
+    }
+
+    #[test]
+    fn eval_renaming_skips_names_in_use() {
+        let expr = Parser::new("apply(func x => func y => +(+(x, y), yone), y)")
+            .parse()
+            .unwrap();
+        let symbolic = Evaluator::new().with_unbound(Unbound::Symbolic);
+        let result = symbolic.eval(&expr).unwrap();
+        assert_eq!("func y1 => y + y1 + yone", format!("{}", result));
+    }
+
+    #[test]
+    fn free_variables() {
+        let mut prog = Parser::new("apply(func x => +(x, y), if b then z else x)");
+        let free = prog.parse().unwrap().free_variables();
+        let expected: HashSet<String> =
+            ["y", "b", "z", "x"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(free, expected);
+
+        let mut prog = Parser::new("func x => func y => +(x, y)");
+        assert!(prog.parse().unwrap().free_variables().is_empty());
+    }
+}
+
+#[cfg(test)]
+mod evaluator_tests {
+    use crate::evaluator::Evaluator;
+    use crate::integer::int;
+    use crate::parser::Parser;
+    use crate::value::{Env, Value};
+
+    #[test]
+    fn eval_returns_value() {
+        let expr = Parser::new("+(1, 2)").parse().unwrap();
+        let result = Evaluator::new().eval(&expr);
+        assert_eq!(result, Ok(Value::Int(int(3))));
+    }
+
+    #[test]
+    fn eval_in_environment() {
+        let expr = Parser::new("if b then +(x, 1) else 0").parse().unwrap();
+        let env = Env::new()
+            .bind("x", Value::Int(int(41)))
+            .bind("b", Value::Bool(true));
+        let result = Evaluator::new().eval_in(&expr, &env);
+        assert_eq!(result, Ok(Value::Int(int(42))));
+    }
+
+    #[test]
+    fn env_inner_binding_shadows_outer() {
+        let env = Env::new()
+            .bind("x", Value::Int(int(1)))
+            .bind("x", Value::Int(int(2)));
+        assert_eq!(env.lookup("x"), Some(&Value::Int(int(2))));
+        assert_eq!(env.lookup("y"), None);
+    }
+
+    #[test]
+    fn eval_free_variables_resolve_lexically() {
+        // `f` sees the `x` from where it was defined, not the one where it is called
+        let expr = Parser::new(
+            "apply(func x => apply(func f => apply(func x => apply(f, 0), 100), func y => x), 1)",
+        )
+        .parse()
+        .unwrap();
+        let result = Evaluator::new().eval(&expr);
+        assert_eq!(result, Ok(Value::Int(int(1))));
+    }
+
+    #[test]
+    fn eval_closure_captures_environment() {
+        let expr = Parser::new("apply(func x => func y => +(x, y), 5)")
+            .parse()
+            .unwrap();
+        let result = Evaluator::new().eval(&expr).unwrap();
+        let Value::Closure(closure) = &result else {
+            panic!("expected a closure, got {:?}", result);
+        };
+        assert_eq!(closure.param(), "y");
+        assert_eq!(closure.env().lookup("x"), Some(&Value::Int(int(5))));
+        assert_eq!("func y => 5 + y", format!("{}", result.to_expression()));
+    }
+
+    #[test]
+    fn eval_curried_function() {
+        let expr = Parser::new(
+            "apply(apply(apply(func a => func b => func c => -(*(a, b), c), 6), 7), 2)",
+        )
+        .parse()
+        .unwrap();
+        let result = Evaluator::new().eval(&expr);
+        assert_eq!(result, Ok(Value::Int(int(40))));
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod value_tests {
+    use crate::error::{ConversionError, Type};
+    use crate::integer::int;
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    #[test]
+    fn display_value() {
+        assert_eq!(format!("{}", Value::Int(int(-3))), "-3");
+        assert_eq!(format!("{}", Value::Bool(true)), "T");
+        assert_eq!(format!("{}", Value::Bool(false)), "F");
+        assert_eq!(format!("{}", Value::Symbol("x".to_string())), "x");
+
+        let closure = Parser::new("func x => x").parse().unwrap().eval().unwrap();
+        assert_eq!(format!("{}", closure), "func x => x");
+    }
+
+    #[test]
+    fn value_accessors() {
+        assert_eq!(Value::Int(int(7)).as_i64(), Some(7));
+        assert_eq!(Value::Int(int(7)).as_bool(), None);
+        assert_eq!(Value::Bool(true).as_bool(), Some(true));
+        assert_eq!(Value::Bool(true).as_i64(), None);
+        assert!(Value::Int(int(7)).as_closure().is_none());
+    }
+
+    #[test]
+    fn value_conversions() {
+        assert_eq!(Value::from(5), Value::Int(int(5)));
+        assert_eq!(Value::from(false), Value::Bool(false));
+        assert_eq!(i64::try_from(Value::Int(int(5))), Ok(5));
+        assert_eq!(bool::try_from(Value::Bool(true)), Ok(true));
+        assert_eq!(
+            i64::try_from(Value::Bool(true)),
+            Err(ConversionError {
+                expected: Type::Integer,
+                value: Value::Bool(true),
+            })
+        );
+    }
+
+    #[test]
+    fn literal_and_computed_values_are_equal() {
+        let literal = Parser::new("4").parse().unwrap().eval();
+        let computed = Parser::new("*(2, 2)").parse().unwrap().eval();
+        assert_eq!(literal, computed);
+    }
+
+    #[test]
+    fn display_conversion_error() {
+        let error = bool::try_from(Value::Int(int(1))).unwrap_err();
+        assert_eq!(format!("{}", error), "expected boolean, found integer '1'");
+    }
+}
+
+#[cfg(test)]
+mod bindings_tests {
+    use crate::error::{EvalError, Type};
+    use crate::evaluator::{Evaluator, Unbound};
+    use crate::integer::int;
+    use crate::parser::Parser;
+    use crate::value::{Bindings, Value};
+
+    #[test]
+    fn eval_with_bindings() {
+        let expr = Parser::new("+(x, 1)").parse().unwrap();
+        let bindings = Bindings::new().with("x", 41);
+        assert_eq!(expr.eval_with(&bindings), Ok(Value::Int(int(42))));
+    }
+
+    #[test]
+    fn eval_formula_with_several_inputs() {
+        let expr = Parser::new("if &(member, <(100, total)) then -(total, 10) else total")
+            .parse()
+            .unwrap();
+        let mut bindings: Bindings = [("total", 250)].into_iter().collect();
+        bindings.insert("member", true);
+        assert_eq!(expr.eval_with(&bindings), Ok(Value::Int(int(240))));
+
+        bindings.insert("member", false);
+        assert_eq!(expr.eval_with(&bindings), Ok(Value::Int(int(250))));
+    }
+
+    #[test]
+    fn eval_missing_binding() {
+        let expr = Parser::new("+(x, y)").parse().unwrap();
+        let bindings = Bindings::new().with("x", 1);
+        assert_eq!(
+            expr.eval_with(&bindings),
+            Err(EvalError::UnboundVariable {
+                name: "y".to_string(),
+                span: None,
+            })
+        );
+    }
+
+    #[test]
+    fn function_parameter_shadows_binding() {
+        let expr = Parser::new("apply(func x => *(x, y), 3)").parse().unwrap();
+        let bindings = Bindings::new().with("x", 100).with("y", 2);
+        assert_eq!(expr.eval_with(&bindings), Ok(Value::Int(int(6))));
+    }
+
+    #[test]
+    fn eval_leaves_unbound_variables_symbolic() {
+        let expr = Parser::new("apply(func f => f, g)").parse().unwrap();
+        let evaluator = Evaluator::new().with_unbound(Unbound::Symbolic);
+        let result = evaluator.eval_with(&expr, &Bindings::new());
+        assert_eq!(result, Ok(Value::Symbol("g".to_string())));
+
+        let expr = Parser::new("+(x, 1)").parse().unwrap();
+        let result = evaluator.eval(&expr);
+        assert!(matches!(
+            result,
+            Err(EvalError::TypeMismatch {
+                found: Type::Variable,
+                ..
+            })
+        ));
+    }
+}
+
+#[cfg(test)]
+mod infix_tests {
+    use super::helpers::{parse_infix, parse_prefix};
+    use crate::error::{Expected, ParseError};
+    use crate::integer::int;
+    use crate::parser::{LexItem, Parser, Syntax};
+    use crate::value::Value;
+
+    #[test]
+    fn infix_multiplication_binds_tighter() {
+        assert_eq!(parse_infix("1 + 2 * 3"), parse_prefix("+(1, *(2, 3))"));
+        assert_eq!(parse_infix("1 * 2 + 3"), parse_prefix("+(*(1, 2), 3)"));
+    }
+
+    #[test]
+    fn infix_left_associative() {
+        assert_eq!(parse_infix("10 - 4 - 3"), parse_prefix("-(-(10, 4), 3)"));
+        assert_eq!(parse_infix("8 / 2 / 2"), parse_prefix("/(/(8, 2), 2)"));
+    }
+
+    #[test]
+    fn infix_comparison_and_logic() {
+        assert_eq!(parse_infix("a < b & c"), parse_prefix("&(<(a, b), c)"));
+        assert_eq!(
+            parse_infix("a & b | c & d"),
+            parse_prefix("|(&(a, b), &(c, d))")
+        );
+        assert_eq!(parse_infix("x + 1 = y"), parse_prefix("=(+(x, 1), y)"));
+    }
+
+    #[test]
+    fn infix_parentheses_group() {
+        assert_eq!(parse_infix("(1 + 2) * 3"), parse_prefix("*(+(1, 2), 3)"));
+        assert_eq!(parse_infix("((x))"), parse_prefix("x"));
+    }
+
+    #[test]
+    fn infix_not_binds_tightest() {
+        assert_eq!(parse_infix("!a & b"), parse_prefix("&(!a, b)"));
+        assert_eq!(parse_infix("!(a & b)"), parse_prefix("!&(a, b)"));
+    }
+
+    #[test]
+    fn infix_keyword_forms() {
+        assert_eq!(
+            parse_infix("if x < 10 then 10 - x else x + 10"),
+            parse_prefix("if <(x, 10) then -(10, x) else +(x, 10)")
+        );
+        assert_eq!(
+            parse_infix("apply(func x => x * x + 1, 2 + 3)"),
+            parse_prefix("apply(func x => +(*(x, x), 1), +(2, 3))")
+        );
+        assert_eq!(
+            parse_infix("1 + if T then 2 else 3 * 4"),
+            parse_prefix("+(1, if T then 2 else *(3, 4))")
+        );
+    }
+
+    #[test]
+    fn infix_eval() {
+        let result = parse_infix("2 + 3 * 4 - 6 / 2").eval();
+        assert_eq!(result, Ok(Value::Int(int(11))));
+    }
+
+    #[test]
+    fn infix_missing_operand() {
+        let result = Parser::new("1 + * 2").with_syntax(Syntax::Infix).parse();
+        assert!(matches!(
+            result,
+            Err(ParseError::UnexpectedToken { expected, .. }) if expected == vec![Expected::Expression]
+        ));
+    }
+
+    #[test]
+    fn infix_unclosed_paren() {
+        let result = Parser::new("(1 + 2 3").with_syntax(Syntax::Infix).parse();
+        assert!(matches!(
+            result,
+            Err(ParseError::UnexpectedToken {
+                found: LexItem::Integer(ref value),
+                ..
+            }) if *value == int(3)
+        ));
+    }
+
+    #[test]
+    fn infix_trailing_operand() {
+        let result = Parser::new("1 2").with_syntax(Syntax::Infix).parse();
+        assert!(matches!(result, Err(ParseError::TrailingInput { .. })));
+    }
+
+    #[test]
+    fn prefix_syntax_is_default() {
+        let result = Parser::new("1 + 2").parse();
+        assert!(matches!(result, Err(ParseError::TrailingInput { .. })));
+    }
+}
+
+#[cfg(test)]
+mod round_trip_tests {
+    use crate::expression::{BinaryOperator, Expression, UnaryOperator};
+    use crate::integer::int;
+    use crate::parser::{Parser, Syntax};
+
+    const PROGRAMS: [&str; 21] = [
+        "+(1, +(1, 1))",
+        "+(+(1, 1), 1)",
+        "-(10, -(4, 3))",
+        "*(+(1, 2), 3)",
+        "+(1, *(2, 3))",
+        "&(<(a, b), c)",
+        "|(&(a, b), &(c, d))",
+        "!&(a, b)",
+        "!!T",
+        "+(if T then 1 else 2, 3)",
+        "+(1, func x => x)",
+        "apply(func x => if =(x, 1) then x else 0, 1)",
+        "if if a then b else c then func f => apply(f, 1) else !x",
+        "apply(func x, y => -(x, y), 10, *(3, 2))",
+        "-(^(2, 2))",
+        "^(-2, 2)",
+        "^(-(1), x)",
+        "-(^(-2, ^(2.5, x)))",
+        "^(-(^(2, 3)), -(x))",
+        "-(-(^(-(0.5), 2)))",
+        "-(^(0, x))",
+    ];
+
+    fn binary(op: BinaryOperator, lhs: Expression, rhs: Expression) -> Expression {
+        Expression::BinaryOp {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }
+    }
+
+    #[test]
+    fn canonical_round_trip() {
+        for program in PROGRAMS {
+            let expr = Parser::new(program).parse().unwrap();
+            let printed = format!("{}", expr.canonical());
+            assert_eq!(printed, program);
+            assert_eq!(Parser::new(&printed).parse(), Ok(expr));
+        }
+    }
+
+    #[test]
+    fn infix_round_trip() {
+        for program in PROGRAMS {
+            let expr = Parser::new(program).parse().unwrap();
+            let printed = format!("{}", expr);
+            let reparsed = Parser::new(&printed).with_syntax(Syntax::Infix).parse();
+            assert_eq!(reparsed, Ok(expr), "printed as {}", printed);
+        }
+    }
+
+    #[test]
+    fn infix_parenthesises_only_where_needed() {
+        let cases = [
+            ("+(+(1, 2), 3)", "1 + 2 + 3"),
+            ("+(1, +(2, 3))", "1 + (2 + 3)"),
+            ("+(1, *(2, 3))", "1 + 2 * 3"),
+            ("*(+(1, 2), 3)", "(1 + 2) * 3"),
+            ("&(<(a, b), c)", "a < b & c"),
+            ("!&(a, b)", "!(a & b)"),
+            ("&(!a, b)", "!a & b"),
+            ("+(if T then 1 else 2, 3)", "(if T then 1 else 2) + 3"),
+            ("+(3, if T then 1 else 2)", "3 + if T then 1 else 2"),
+            ("+(+(1, func x => x), 2)", "1 + (func x => x) + 2"),
+        ];
+        for (program, infix) in cases {
+            let expr = Parser::new(program).parse().unwrap();
+            assert_eq!(format!("{}", expr), infix);
+        }
+    }
+
+    #[test]
+    fn round_trip_constructed_expression() {
+        let expr = Expression::UnaryOp {
+            op: UnaryOperator::Not,
+            child: Box::new(binary(
+                BinaryOperator::LessThan,
+                binary(
+                    BinaryOperator::Subtract,
+                    Expression::Integer(int(1)),
+                    binary(
+                        BinaryOperator::Subtract,
+                        Expression::Integer(int(2)),
+                        Expression::Integer(int(3)),
+                    ),
+                ),
+                Expression::Variable("x".to_string()),
+            )),
+        };
+        let canonical = format!("{}", expr.canonical());
+        assert_eq!(canonical, "!<(-(1, -(2, 3)), x)");
+        assert_eq!(Parser::new(&canonical).parse(), Ok(expr.clone()));
+
+        let infix = format!("{}", expr);
+        assert_eq!(infix, "!(1 - (2 - 3) < x)");
+        let reparsed = Parser::new(&infix).with_syntax(Syntax::Infix).parse();
+        assert_eq!(reparsed, Ok(expr));
+    }
+}
+
+#[cfg(test)]
+mod identifier_tests {
+    #[cfg(not(feature = "bigint"))]
+    use crate::error::LexError;
+    use crate::integer::int;
+    use crate::parser::{lex, LexItem, Parser};
+    #[cfg(not(feature = "bigint"))]
+    use crate::span::{Position, Span};
+    use crate::value::{Bindings, Value};
+
+    #[test]
+    fn lex_identifiers_with_digits_and_underscores() {
+        let result = lex("x1 my_var _tmp Total");
+        assert_eq!(
+            result,
+            Ok(vec![
+                LexItem::Variable("x1".to_string()),
+                LexItem::Variable("my_var".to_string()),
+                LexItem::Variable("_tmp".to_string()),
+                LexItem::Variable("Total".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn lex_unicode_identifiers() {
+        let result = lex("größe π");
+        assert_eq!(
+            result,
+            Ok(vec![
+                LexItem::Variable("größe".to_string()),
+                LexItem::Variable("π".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn lex_identifiers_starting_with_boolean_letters() {
+        let result = lex("Tax Fee");
+        assert_eq!(
+            result,
+            Ok(vec![
+                LexItem::Variable("Tax".to_string()),
+                LexItem::Variable("Fee".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn lex_boolean_words() {
+        let result = lex("true false T F");
+        assert_eq!(
+            result,
+            Ok(vec![
+                LexItem::Boolean(true),
+                LexItem::Boolean(false),
+                LexItem::Boolean(true),
+                LexItem::Boolean(false),
+            ])
+        );
+    }
+
+    #[test]
+    fn lex_keywords_are_not_prefixes() {
+        let result = lex("iffy applyx");
+        assert_eq!(
+            result,
+            Ok(vec![
+                LexItem::Variable("iffy".to_string()),
+                LexItem::Variable("applyx".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "bigint"))]
+    fn lex_integer_out_of_range() {
+        let result = lex("+(1, 99999999999999999999)");
+        assert_eq!(
+            result,
+            Err(LexError::IntegerOutOfRange {
+                literal: "99999999999999999999".to_string(),
+                span: Span::new(5, 25),
+                position: Position { line: 1, column: 6 },
+            })
+        );
+    }
+
+    #[test]
+    fn lex_largest_integer() {
+        let result = lex("9223372036854775807");
+        assert_eq!(result, Ok(vec![LexItem::Integer(int(i64::MAX))]));
+    }
+
+    #[test]
+    fn eval_formula_with_long_names() {
+        let expr = Parser::new("*(unit_price, Qty2)").parse().unwrap();
+        let bindings = Bindings::new().with("unit_price", 3).with("Qty2", 4);
+        assert_eq!(expr.eval_with(&bindings), Ok(Value::Int(int(12))));
+    }
+}
+
+#[cfg(test)]
+mod negation_tests {
+    #[cfg(not(feature = "bigint"))]
+    use crate::error::EvalError;
+    #[cfg(not(feature = "bigint"))]
+    use crate::evaluator::Arithmetic;
+    #[cfg(not(feature = "bigint"))]
+    use crate::expression::Operator;
+    use crate::expression::{BinaryOperator, Expression, UnaryOperator};
+    use crate::integer::int;
+    use crate::parser::{lex, LexItem, Parser, Syntax};
+    use crate::value::Value;
+
+    fn negate(child: Expression) -> Expression {
+        Expression::UnaryOp {
+            op: UnaryOperator::Negate,
+            child: Box::new(child),
+        }
+    }
+
+    #[test]
+    fn lex_negative_literal() {
+        assert_eq!(lex("-5"), Ok(vec![LexItem::Integer(int(-5))]));
+        assert_eq!(
+            lex("-9223372036854775808"),
+            Ok(vec![LexItem::Integer(int(i64::MIN))])
+        );
+        assert_eq!(
+            lex("+(1, -5)"),
+            Ok(vec![
+                LexItem::BinaryOp(BinaryOperator::Add),
+                LexItem::OpenParen,
+                LexItem::Integer(int(1)),
+                LexItem::Comma,
+                LexItem::Integer(int(-5)),
+                LexItem::CloseParen,
+            ])
+        );
+    }
+
+    #[test]
+    fn lex_minus_after_operand_is_subtraction() {
+        assert_eq!(
+            lex("x -1"),
+            Ok(vec![
+                LexItem::Variable("x".to_string()),
+                LexItem::BinaryOp(BinaryOperator::Subtract),
+                LexItem::Integer(int(1)),
+            ])
+        );
+        assert_eq!(
+            lex("3 - -1"),
+            Ok(vec![
+                LexItem::Integer(int(3)),
+                LexItem::BinaryOp(BinaryOperator::Subtract),
+                LexItem::Integer(int(-1)),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_prefix_negation() {
+        let x = Expression::Variable("x".to_string());
+        assert_eq!(Parser::new("-x").parse(), Ok(negate(x.clone())));
+        assert_eq!(Parser::new("-(x)").parse(), Ok(negate(x.clone())));
+        assert_eq!(
+            Parser::new("- 5").parse(),
+            Ok(negate(Expression::Integer(int(5))))
+        );
+        assert_eq!(Parser::new("-5").parse(), Ok(Expression::Integer(int(-5))));
+        assert_eq!(
+            Parser::new("-(x, 1)").parse(),
+            Ok(Expression::BinaryOp {
+                op: BinaryOperator::Subtract,
+                lhs: Box::new(x),
+                rhs: Box::new(Expression::Integer(int(1))),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_infix_negation() {
+        let parse = |program: &str| Parser::new(program).with_syntax(Syntax::Infix).parse();
+        assert_eq!(parse("-x * 2"), Parser::new("*(-x, 2)").parse());
+        assert_eq!(parse("-(x * 2)"), Parser::new("-(*(x, 2))").parse());
+        assert_eq!(parse("1 - -2"), Parser::new("-(1, -2)").parse());
+        assert_eq!(parse("x-1"), Parser::new("-(x, 1)").parse());
+    }
+
+    #[test]
+    fn eval_negation() {
+        let result = Parser::new("-(+(2, 3))").parse().unwrap().eval();
+        assert_eq!(result, Ok(Value::Int(int(-5))));
+        let result = Parser::new("apply(func x => -x, -7)")
+            .parse()
+            .unwrap()
+            .eval();
+        assert_eq!(result, Ok(Value::Int(int(7))));
+    }
+
+    #[test]
+    #[cfg(not(feature = "bigint"))]
+    fn eval_negate_min_overflows() {
+        let expr = negate(Expression::Integer(int(i64::MIN)));
+        assert_eq!(
+            expr.eval(),
+            Err(EvalError::Overflow {
+                op: Operator::Unary(UnaryOperator::Negate),
+                expr: expr.clone(),
+                span: None,
+            })
+        );
+        assert_eq!(
+            expr.eval_with_arithmetic(Arithmetic::Wrapping),
+            Ok(Value::Int(int(i64::MIN)))
+        );
+        assert_eq!(
+            expr.eval_with_arithmetic(Arithmetic::Saturating),
+            Ok(Value::Int(int(i64::MAX)))
+        );
+    }
+
+    #[test]
+    fn negation_round_trips() {
+        let cases = [
+            negate(Expression::Integer(int(5))),
+            negate(Expression::Integer(int(-5))),
+            negate(negate(Expression::Variable("x".to_string()))),
+            Expression::Integer(int(i64::MIN)),
+            Expression::BinaryOp {
+                op: BinaryOperator::Subtract,
+                lhs: Box::new(Expression::Integer(int(-1))),
+                rhs: Box::new(Expression::Integer(int(-2))),
+            },
+        ];
+        for expr in cases {
+            let canonical = format!("{}", expr.canonical());
+            assert_eq!(Parser::new(&canonical).parse(), Ok(expr.clone()));
+            let infix = format!("{}", expr);
+            let reparsed = Parser::new(&infix).with_syntax(Syntax::Infix).parse();
+            assert_eq!(reparsed, Ok(expr), "printed as {}", infix);
+        }
+    }
+
+    #[test]
+    fn display_negation() {
+        assert_eq!(format!("{}", negate(Expression::Integer(int(5)))), "-(5)");
+        let expr = negate(Expression::Variable("x".to_string()));
+        assert_eq!(format!("{}", expr), "-x");
+        assert_eq!(format!("{}", expr.canonical()), "-(x)");
+    }
+}
+
+#[cfg(test)]
+mod comment_tests {
+    use crate::error::LexError;
+    use crate::expression::{BinaryOperator, Expression};
+    use crate::integer::int;
+    use crate::parser::{lex, lex_spanned_with_trivia, lex_with_trivia, LexItem, Parser, Token};
+    use crate::span::{Position, Span};
+    use crate::value::Value;
+
+    #[test]
+    fn lex_newlines_and_unicode_whitespace() {
+        assert_eq!(
+            lex("+(1,\r\n\u{a0}2)"),
+            Ok(vec![
+                LexItem::BinaryOp(BinaryOperator::Add),
+                LexItem::OpenParen,
+                LexItem::Integer(int(1)),
+                LexItem::Comma,
+                LexItem::Integer(int(2)),
+                LexItem::CloseParen,
+            ])
+        );
+    }
+
+    #[test]
+    fn lex_skips_line_comments() {
+        assert_eq!(
+            lex("# leading\n1 // trailing\n"),
+            Ok(vec![LexItem::Integer(int(1))])
+        );
+    }
+
+    #[test]
+    fn lex_skips_nested_block_comments() {
+        assert_eq!(
+            lex("/* outer /* inner */ still outer */ 1 /**/"),
+            Ok(vec![LexItem::Integer(int(1))])
+        );
+    }
+
+    #[test]
+    fn lex_slash_is_still_division() {
+        assert_eq!(
+            lex("/(4, 2)").unwrap().first(),
+            Some(&LexItem::BinaryOp(BinaryOperator::Divide))
+        );
+    }
+
+    #[test]
+    fn lex_with_trivia_keeps_comments() {
+        assert_eq!(
+            lex_with_trivia("1 # one\n/* two */ 2"),
+            Ok(vec![
+                LexItem::Integer(int(1)),
+                LexItem::Comment("# one".to_string()),
+                LexItem::Comment("/* two */".to_string()),
+                LexItem::Integer(int(2)),
+            ])
+        );
+    }
+
+    #[test]
+    fn lex_spanned_with_trivia_keeps_comment_spans() {
+        let source = "1 # one\n/* two */ 2";
+        let tokens = lex_spanned_with_trivia(source).unwrap();
+        assert_eq!(
+            tokens[1],
+            Token {
+                item: LexItem::Comment("# one".to_string()),
+                span: Span::new(2, 7),
+            }
+        );
+        let texts: Vec<&str> = tokens
+            .iter()
+            .map(|token| &source[token.span.start..token.span.end])
+            .collect();
+        assert_eq!(texts, vec!["1", "# one", "/* two */", "2"]);
+    }
+
+    #[test]
+    fn lex_unterminated_block_comment() {
+        assert_eq!(
+            lex("1\n/* /* */"),
+            Err(LexError::UnterminatedComment {
+                span: Span::new(2, 10),
+                position: Position { line: 2, column: 1 },
+            })
+        );
+        assert_eq!(
+            lex("1\n/* /* */").unwrap_err().to_string(),
+            "2:1: unterminated block comment"
+        );
+    }
+
+    #[test]
+    fn comment_does_not_make_minus_a_subtraction() {
+        assert_eq!(
+            lex("x /* c */ -1"),
+            Ok(vec![
+                LexItem::Variable("x".to_string()),
+                LexItem::BinaryOp(BinaryOperator::Subtract),
+                LexItem::Integer(int(1)),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_multi_line_program() {
+        let program = "# doubles its argument\n\
+                       apply(\n    func x => *(x, 2), // body\n    21\n)\n";
+        let expr = Parser::new(program).parse().unwrap();
+        assert!(matches!(expr, Expression::Apply { .. }));
+        assert_eq!(expr.eval(), Ok(Value::Int(int(42))));
+    }
+}
+
+#[cfg(test)]
+mod span_tests {
+    use crate::error::{EvalError, Type};
+    use crate::evaluator::Evaluator;
+    use crate::expression::BinaryOperator;
+    use crate::integer::int;
+    use crate::parser::{lex_spanned, LexItem, Parser, Syntax, Token};
+    use crate::span::{Position, Span};
+    use crate::{parse_and_eval, Error};
+
+    #[test]
+    fn lex_spanned_records_byte_ranges() {
+        assert_eq!(
+            lex_spanned("+(é, 10)"),
+            Ok(vec![
+                Token {
+                    item: LexItem::BinaryOp(BinaryOperator::Add),
+                    span: Span::new(0, 1),
+                },
+                Token {
+                    item: LexItem::OpenParen,
+                    span: Span::new(1, 2),
+                },
+                Token {
+                    item: LexItem::Variable("é".to_string()),
+                    span: Span::new(2, 4),
+                },
+                Token {
+                    item: LexItem::Comma,
+                    span: Span::new(4, 5),
+                },
+                Token {
+                    item: LexItem::Integer(int(10)),
+                    span: Span::new(6, 8),
+                },
+                Token {
+                    item: LexItem::CloseParen,
+                    span: Span::new(8, 9),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn prefix_spans_in_preorder() {
+        let source = "+(1, *(x, 3))";
+        let (expr, spans) = Parser::new(source).parse_spanned().unwrap();
+        let texts: Vec<&str> = spans
+            .iter()
+            .map(|span| &source[span.start..span.end])
+            .collect();
+        assert_eq!(texts, vec![source, "1", "*(x, 3)", "x", "3"]);
+        assert_eq!(spans.len(), 5);
+        assert_eq!(expr.children().len(), 2);
+    }
+
+    #[test]
+    fn infix_spans_in_preorder() {
+        let source = "(1 + 2) * -x";
+        let (_, spans) = Parser::new(source)
+            .with_syntax(Syntax::Infix)
+            .parse_spanned()
+            .unwrap();
+        let texts: Vec<&str> = spans
+            .iter()
+            .map(|span| &source[span.start..span.end])
+            .collect();
+        assert_eq!(texts, vec![source, "1 + 2", "1", "2", "-x", "x"]);
+    }
+
+    #[test]
+    fn negated_power_base_spans() {
+        let source = "-2 ^ x * 3";
+        let (_, spans) = Parser::new(source)
+            .with_syntax(Syntax::Infix)
+            .parse_spanned()
+            .unwrap();
+        let texts: Vec<&str> = spans
+            .iter()
+            .map(|span| &source[span.start..span.end])
+            .collect();
+        assert_eq!(texts, vec![source, "-2 ^ x", "2 ^ x", "2", "x", "3"]);
+    }
+
+    #[test]
+    fn keyword_expression_spans() {
+        let source = "apply(func x => if x then 1 else 2, T)";
+        let (_, spans) = Parser::new(source).parse_spanned().unwrap();
+        assert_eq!(spans.root(), Some(Span::new(0, source.len())));
+        assert_eq!(spans.get(1), Some(Span::new(6, 34)));
+        assert_eq!(spans.get(2), Some(Span::new(16, 34)));
+        assert_eq!(spans.get(7), None);
+    }
+
+    #[test]
+    fn eval_error_points_at_source() {
+        let source = "+(1,\n  if 1 then 2 else 3)";
+        let (expr, spans) = Parser::new(source).parse_spanned().unwrap();
+        let error = Evaluator::new().eval_spanned(&expr, &spans).unwrap_err();
+        assert!(matches!(
+            error,
+            EvalError::NonBooleanCondition {
+                found: Type::Integer,
+                ..
+            }
+        ));
+        let span = error.span().unwrap();
+        assert_eq!(&source[span.start..span.end], "1");
+        assert_eq!(
+            Position::locate(source, span.start),
+            Position { line: 2, column: 6 }
+        );
+    }
+
+    #[test]
+    fn eval_error_inside_function_body() {
+        match parse_and_eval("apply(func y => /(y, 0), 4)") {
+            Err(Error::Eval(error @ EvalError::DivisionByZero { .. })) => {
+                assert_eq!(error.span(), Some(Span::new(16, 23)));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unspanned_eval_error_has_no_span() {
+        let expr = Parser::new("z").parse().unwrap();
+        assert_eq!(expr.eval().unwrap_err().span(), None);
+    }
+}
+
+#[cfg(test)]
+mod let_tests {
+    use crate::integer::int;
+    use std::collections::HashMap;
+
+    use crate::error::{Expected, ParseError};
+    use crate::expression::{substitute, BinaryOperator, Expression};
+    use crate::parser::{lex, LexItem, Parser, Syntax};
+    use crate::value::Value;
+
+    fn var(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+
+    fn let_expr(name: &str, value: Expression, body: Expression) -> Expression {
+        Expression::Let {
+            name: name.to_string(),
+            value: Box::new(value),
+            body: Box::new(body),
+        }
+    }
+
+    #[test]
+    fn lex_let() {
+        assert_eq!(
+            lex("let x = 1 in x"),
+            Ok(vec![
+                LexItem::Let,
+                LexItem::Variable("x".to_string()),
+                LexItem::BinaryOp(BinaryOperator::Equals),
+                LexItem::Integer(int(1)),
+                LexItem::In,
+                LexItem::Variable("x".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_let() {
+        let expr = Parser::new("let x = +(1, 2) in *(x, x)").parse().unwrap();
+        assert_eq!(
+            expr,
+            let_expr(
+                "x",
+                Expression::BinaryOp {
+                    op: BinaryOperator::Add,
+                    lhs: Box::new(Expression::Integer(int(1))),
+                    rhs: Box::new(Expression::Integer(int(2))),
+                },
+                Expression::BinaryOp {
+                    op: BinaryOperator::Multiply,
+                    lhs: Box::new(var("x")),
+                    rhs: Box::new(var("x")),
+                },
+            )
+        );
+        assert_eq!(expr.eval(), Ok(Value::Int(int(9))));
+    }
+
+    #[test]
+    fn parse_let_infix() {
+        let expr = Parser::new("let x = 2 in let y = x * 3 in y - x")
+            .with_syntax(Syntax::Infix)
+            .parse()
+            .unwrap();
+        assert_eq!(expr.eval(), Ok(Value::Int(int(4))));
+    }
+
+    #[test]
+    fn parse_let_missing_in() {
+        let error = Parser::new("let x = 1 x").parse().unwrap_err();
+        assert!(matches!(
+            error,
+            ParseError::UnexpectedToken {
+                ref expected,
+                ..
+            } if expected == &vec![Expected::In]
+        ));
+        assert_eq!(
+            Parser::new("let x 1 in x").parse().unwrap_err().to_string(),
+            "1:7: expected '=', found '1'"
+        );
+    }
+
+    #[test]
+    fn let_shadows_and_scopes() {
+        // The value is evaluated outside the binding, the body inside it
+        let expr = Parser::new("let x = 1 in let x = +(x, 1) in x")
+            .parse()
+            .unwrap();
+        assert_eq!(expr.eval(), Ok(Value::Int(int(2))));
+        assert_eq!(
+            Parser::new("let x = x in x")
+                .parse()
+                .unwrap()
+                .free_variables()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec!["x".to_string()]
+        );
+    }
+
+    #[test]
+    fn let_matches_apply() {
+        let by_let = Parser::new("let f = func y => *(y, 2) in apply(f, 21)")
+            .parse()
+            .unwrap();
+        let by_apply = Parser::new("apply(func f => apply(f, 21), func y => *(y, 2))")
+            .parse()
+            .unwrap();
+        assert_eq!(by_let.eval(), by_apply.eval());
+    }
+
+    #[test]
+    fn display_let() {
+        let expr = Parser::new("+(1, let x = 2 in x)").parse().unwrap();
+        assert_eq!(format!("{}", expr), "1 + let x = 2 in x");
+        assert_eq!(format!("{}", expr.canonical()), "+(1, let x = 2 in x)");
+
+        let expr = Parser::new("+(let x = 2 in x, 1)").parse().unwrap();
+        assert_eq!(format!("{}", expr), "(let x = 2 in x) + 1");
+        let reparsed = Parser::new(&expr.to_string())
+            .with_syntax(Syntax::Infix)
+            .parse()
+            .unwrap();
+        assert_eq!(reparsed, expr);
+    }
+
+    #[test]
+    fn substitute_avoids_capture_by_let() {
+        let expr = let_expr("y", var("x"), var("x"));
+        let replacements = HashMap::from([("x".to_string(), var("y"))]);
+        assert_eq!(
+            substitute(&expr, &replacements),
+            let_expr("y1", var("y"), var("y"))
+        );
+    }
+}
+
+#[cfg(test)]
+mod recursion_tests {
+    use crate::integer::int;
+    use std::collections::HashMap;
+
+    use super::helpers::eval_infix;
+    use crate::error::{EvalError, Expected, ParseError};
+    use crate::expression::{substitute, Expression};
+    use crate::parser::{Parser, Syntax};
+    use crate::value::Value;
+
+    #[test]
+    fn factorial() {
+        let program = "let rec fact = func n => if <(n, 1) then 1 else *(n, apply(fact, -(n, 1))) in apply(fact, 10)";
+        let expr = Parser::new(program).parse().unwrap();
+        assert_eq!(expr.eval(), Ok(Value::Int(int(3628800))));
+    }
+
+    #[test]
+    fn fibonacci_infix() {
+        let program = "let rec fib = func n => \
+                       if n < 2 then n else apply(fib, n - 1) + apply(fib, n - 2) \
+                       in apply(fib, 20)";
+        assert_eq!(eval_infix(program), Ok(Value::Int(int(6765))));
+    }
+
+    #[test]
+    fn deep_recursion_does_not_overflow_the_stack() {
+        let program = "let rec sum = func n => if n = 0 then 0 else n + apply(sum, n - 1) \
+                       in apply(sum, 100000)";
+        assert_eq!(eval_infix(program), Ok(Value::Int(int(5000050000))));
+    }
+
+    #[test]
+    fn deep_tail_recursion() {
+        let program = "let rec count = func n => if n = 0 then T else apply(count, n - 1) \
+                       in apply(count, 300000)";
+        assert_eq!(eval_infix(program), Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn recursive_function_sees_outer_bindings() {
+        let program = "let step = 3 in \
+                       let rec down = func n => if n < 1 then n else apply(down, n - step) \
+                       in apply(down, 10)";
+        assert_eq!(eval_infix(program), Ok(Value::Int(int(-2))));
+    }
+
+    #[test]
+    fn plain_let_is_not_recursive() {
+        let program = "let f = func n => apply(f, n) in apply(f, 1)";
+        assert!(matches!(
+            eval_infix(program),
+            Err(EvalError::UnboundVariable { ref name, .. }) if name == "f"
+        ));
+    }
+
+    #[test]
+    fn let_rec_requires_a_function() {
+        let error = Parser::new("let rec x = 1 in x").parse().unwrap_err();
+        assert!(matches!(
+            error,
+            ParseError::UnexpectedToken { ref expected, .. } if expected == &vec![Expected::Func]
+        ));
+    }
+
+    #[test]
+    fn display_recursive_closure() {
+        let program = "let k = 2 in let rec f = func n => apply(f, n * k) in f";
+        let value = eval_infix(program).unwrap();
+        assert_eq!(value.as_closure().unwrap().name(), Some("f"));
+        assert_eq!(
+            value.to_string(),
+            "let rec f = func n => apply(f, n * 2) in f"
+        );
+        let expr = Parser::new(&value.to_string())
+            .with_syntax(Syntax::Infix)
+            .parse()
+            .unwrap();
+        assert_eq!(
+            format!("{}", expr.canonical()),
+            "let rec f = func n => apply(f, *(n, 2)) in f"
+        );
+    }
+
+    #[test]
+    fn free_variables_of_let_rec() {
+        let expr = Parser::new("let rec f = func n => apply(f, +(n, m)) in apply(f, k)")
+            .parse()
+            .unwrap();
+        let mut free: Vec<String> = expr.free_variables().into_iter().collect();
+        free.sort();
+        assert_eq!(free, vec!["k".to_string(), "m".to_string()]);
+    }
+
+    #[test]
+    fn substitute_renames_let_rec_name_everywhere() {
+        let expr = Parser::new("let rec f = func n => apply(f, x) in apply(f, x)")
+            .parse()
+            .unwrap();
+        let replacements =
+            HashMap::from([("x".to_string(), Expression::Variable("f".to_string()))]);
+        assert_eq!(
+            format!("{}", substitute(&expr, &replacements).canonical()),
+            "let rec f1 = func n => apply(f1, f) in apply(f1, f)"
+        );
+    }
+}
+
+#[cfg(test)]
+mod curry_tests {
+    use super::helpers::parse_prefix;
+    use crate::error::{EvalError, Expected, ParseError};
+    use crate::evaluator::Evaluator;
+    use crate::integer::int;
+    use crate::parser::{Parser, Syntax};
+    use crate::span::Span;
+    use crate::value::Value;
+    use crate::{parse_and_eval, Error};
+
+    #[test]
+    fn multi_parameter_func_is_curried() {
+        assert_eq!(
+            parse_prefix("func x, y => -(x, y)"),
+            parse_prefix("func x => func y => -(x, y)")
+        );
+    }
+
+    #[test]
+    fn multi_argument_apply_is_curried() {
+        assert_eq!(
+            parse_prefix("apply(f, 1, 2)"),
+            parse_prefix("apply(apply(f, 1), 2)")
+        );
+        assert_eq!(
+            parse_prefix("apply(func x, y => -(x, y), 10, 3)").eval(),
+            Ok(Value::Int(int(7)))
+        );
+    }
+
+    #[test]
+    fn partial_application() {
+        let program =
+            "let sub = func x, y => x - y in let from10 = apply(sub, 10) in apply(from10, 4)";
+        let expr = Parser::new(program)
+            .with_syntax(Syntax::Infix)
+            .parse()
+            .unwrap();
+        assert_eq!(expr.eval(), Ok(Value::Int(int(6))));
+    }
+
+    #[test]
+    fn partially_applied_closure_display() {
+        let value = parse_prefix("apply(func x, y, z => +(x, +(y, z)), 1)")
+            .eval()
+            .unwrap();
+        assert_eq!(value.to_string(), "func y, z => 1 + (y + z)");
+    }
+
+    #[test]
+    fn recursive_multi_parameter_function() {
+        let program = "let rec pow = func b, e => if e = 0 then 1 else b * apply(pow, b, e - 1) \
+                       in apply(pow, 2, 10)";
+        let expr = Parser::new(program)
+            .with_syntax(Syntax::Infix)
+            .parse()
+            .unwrap();
+        assert_eq!(expr.eval(), Ok(Value::Int(int(1024))));
+        assert_eq!(
+            format!("{}", expr.canonical()),
+            "let rec pow = func b, e => if =(e, 0) then 1 else *(b, apply(pow, b, -(e, 1))) in apply(pow, 2, 10)"
+        );
+    }
+
+    #[test]
+    fn too_many_arguments() {
+        let expr = parse_prefix("apply(func x => x, 1, 2, 3)");
+        assert_eq!(
+            expr.eval(),
+            Err(EvalError::TooManyArguments {
+                expected: 1,
+                found: 3,
+                expr: expr.clone(),
+                span: None,
+            })
+        );
+        assert_eq!(
+            expr.eval().unwrap_err().to_string(),
+            "too many arguments in 'apply(func x => x, 1, 2, 3)': the function takes 1 but 3 were supplied"
+        );
+    }
+
+    #[test]
+    fn too_many_arguments_span() {
+        match parse_and_eval("+(1, apply(func x, y => +(x, y), 1, 2, 3))") {
+            Err(Error::Eval(error @ EvalError::TooManyArguments { .. })) => {
+                assert_eq!(error.span(), Some(Span::new(5, 41)));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn single_argument_non_function_is_not_an_arity_error() {
+        assert!(matches!(
+            parse_prefix("apply(1, 2)").eval(),
+            Err(EvalError::NotAFunction { .. })
+        ));
+    }
+
+    #[test]
+    fn curried_spans() {
+        let source = "apply(func x, y => x, 1, 2)";
+        let (_, spans) = Parser::new(source).parse_spanned().unwrap();
+        let texts: Vec<&str> = spans
+            .iter()
+            .map(|span| &source[span.start..span.end])
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                source,
+                source,
+                "func x, y => x",
+                "func x, y => x",
+                "x",
+                "1",
+                "2",
+            ]
+        );
+    }
+
+    #[test]
+    fn curried_call_errors_point_at_whole_expressions() {
+        let span_text = |source: &'static str| {
+            let (expr, spans) = Parser::new(source).parse_spanned().unwrap();
+            let error = Evaluator::new().eval_spanned(&expr, &spans).unwrap_err();
+            let span = error.span().unwrap();
+            (error, &source[span.start..span.end])
+        };
+
+        // The first application returns 1, which cannot take the rest
+        let (error, text) = span_text("+(apply(func x => x, 1, 2, 3), 4)");
+        assert!(matches!(
+            error,
+            EvalError::TooManyArguments {
+                expected: 1,
+                found: 3,
+                ..
+            }
+        ));
+        assert_eq!(text, "apply(func x => x, 1, 2, 3)");
+        let (error, text) = span_text("apply(1, 2, 3)");
+        assert!(matches!(error, EvalError::NotAFunction { .. }));
+        assert_eq!(text, "1");
+
+        // The first application of a curried function fails in its body
+        let source = "let rec f = func a, b => +(a, T) in apply(f, 1, 2)";
+        let (_, spans) = Parser::new(source).parse_spanned().unwrap();
+        let texts: Vec<&str> = spans
+            .iter()
+            .map(|span| &source[span.start..span.end])
+            .collect();
+        assert_eq!(texts[1], "func a, b => +(a, T)");
+        assert_eq!(texts[5], "apply(f, 1, 2)");
+        assert_eq!(texts[6], "apply(f, 1, 2)");
+        assert_eq!(span_text(source).1, "T");
+    }
+
+    #[test]
+    fn parse_errors_mention_commas() {
+        let error = Parser::new("func x y => x").parse().unwrap_err();
+        assert!(matches!(
+            error,
+            ParseError::UnexpectedToken { ref expected, .. }
+                if expected == &vec![Expected::Comma, Expected::Arrow]
+        ));
+        assert_eq!(
+            Parser::new("apply(f, 1 2)")
+                .parse()
+                .unwrap_err()
+                .to_string(),
+            "1:12: expected ',' or ')', found '2'"
+        );
+    }
+}
+
+#[cfg(test)]
+mod comparison_tests {
+    use super::helpers::{eval_infix, eval_prefix};
+    use crate::error::{EvalError, Type};
+    use crate::expression::{BinaryOperator, Expression, Operator, UnaryOperator};
+    use crate::integer::int;
+    use crate::parser::{lex, LexItem, Parser, Syntax};
+    use crate::value::Value;
+
+    #[test]
+    fn lex_comparison_operators() {
+        assert_eq!(
+            lex("< > <= >= = != => !"),
+            Ok(vec![
+                LexItem::BinaryOp(BinaryOperator::LessThan),
+                LexItem::BinaryOp(BinaryOperator::GreaterThan),
+                LexItem::BinaryOp(BinaryOperator::LessEqual),
+                LexItem::BinaryOp(BinaryOperator::GreaterEqual),
+                LexItem::BinaryOp(BinaryOperator::Equals),
+                LexItem::BinaryOp(BinaryOperator::NotEquals),
+                LexItem::Arrow,
+                LexItem::UnaryOp(UnaryOperator::Not),
+            ])
+        );
+        // The longest operator wins, as with "=>"
+        assert_eq!(
+            lex("!=(1, 2)").unwrap()[0],
+            LexItem::BinaryOp(BinaryOperator::NotEquals)
+        );
+        assert_eq!(
+            lex("<=-1"),
+            Ok(vec![
+                LexItem::BinaryOp(BinaryOperator::LessEqual),
+                LexItem::Integer(int(-1)),
+            ])
+        );
+    }
+
+    #[test]
+    fn integer_comparisons() {
+        let cases = [
+            (">(2, 1)", true),
+            (">(1, 1)", false),
+            ("<=(1, 1)", true),
+            ("<=(2, 1)", false),
+            (">=(1, 1)", true),
+            (">=(0, 1)", false),
+            ("!=(1, 2)", true),
+            ("!=(1, 1)", false),
+        ];
+        for (program, expected) in cases {
+            assert_eq!(
+                eval_prefix(program),
+                Ok(Value::Bool(expected)),
+                "{}",
+                program
+            );
+        }
+    }
+
+    #[test]
+    fn infix_comparisons() {
+        assert_eq!(eval_infix("1 + 1 >= 2 & 3 != 4"), Ok(Value::Bool(true)));
+        assert_eq!(eval_infix("2 * 3 <= 5 | 1 > 2"), Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn boolean_equality() {
+        assert_eq!(eval_prefix("=(T, T)"), Ok(Value::Bool(true)));
+        assert_eq!(eval_prefix("=(T, F)"), Ok(Value::Bool(false)));
+        assert_eq!(eval_prefix("!=(<(1, 2), F)"), Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn equality_across_types_is_false() {
+        assert_eq!(eval_prefix("=(1, T)"), Ok(Value::Bool(false)));
+        assert_eq!(eval_prefix("!=(func x => x, 0)"), Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn function_equality_is_structural() {
+        assert_eq!(
+            eval_prefix("=(func x => x, func x => x)"),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            eval_prefix("=(func x => x, func y => y)"),
+            Ok(Value::Bool(false))
+        );
+        assert_eq!(
+            eval_infix("let a = 1 in let f = func x => x + a in let a = 2 in f = func x => x + a"),
+            Ok(Value::Bool(false))
+        );
+    }
+
+    #[test]
+    fn ordering_requires_integers() {
+        assert_eq!(
+            eval_prefix(">=(T, 1)"),
+            Err(EvalError::TypeMismatch {
+                op: Operator::Binary(BinaryOperator::GreaterEqual),
+                expected: Type::Integer,
+                found: Type::Boolean,
+                expr: Expression::Boolean(true),
+                span: None,
+            })
+        );
+    }
+
+    #[test]
+    fn display_comparisons() {
+        let expr = Parser::new("!(a <= b = (c != d))")
+            .with_syntax(Syntax::Infix)
+            .parse()
+            .unwrap();
+        assert_eq!(format!("{}", expr), "!(a <= b = (c != d))");
+        assert_eq!(format!("{}", expr.canonical()), "! =(<=(a, b), !=(c, d))");
+        assert_eq!(Parser::new(&expr.canonical().to_string()).parse(), Ok(expr));
+    }
+}
+
+#[cfg(test)]
+mod math_tests {
+    use super::helpers::{eval_infix, parse_infix};
+    use crate::error::{EvalError, Expected, ParseError, Type};
+    #[cfg(not(feature = "bigint"))]
+    use crate::evaluator::{Arithmetic, Evaluator};
+    use crate::expression::{BinaryOperator, Builtin, Expression, Operator, UnaryOperator};
+    use crate::integer::int;
+    use crate::parser::{lex, LexItem, Parser};
+    use crate::value::Value;
+
+    #[test]
+    fn lex_remainder_and_power() {
+        assert_eq!(
+            lex("% ^"),
+            Ok(vec![
+                LexItem::BinaryOp(BinaryOperator::Remainder),
+                LexItem::BinaryOp(BinaryOperator::Power),
+            ])
+        );
+    }
+
+    #[test]
+    fn remainder_truncates_and_mod_is_euclidean() {
+        assert_eq!(eval_infix("7 % 3"), Ok(Value::Int(int(1))));
+        assert_eq!(eval_infix("-7 % 3"), Ok(Value::Int(int(-1))));
+        assert_eq!(eval_infix("7 % -3"), Ok(Value::Int(int(1))));
+        assert_eq!(eval_infix("mod(-7, 3)"), Ok(Value::Int(int(2))));
+        assert_eq!(eval_infix("mod(7, -3)"), Ok(Value::Int(int(1))));
+        assert_eq!(
+            eval_infix("-9223372036854775808 % -1"),
+            Ok(Value::Int(int(0)))
+        );
+    }
+
+    #[test]
+    fn zero_modulus() {
+        for program in ["%(1, 0)", "mod(1, 0)"] {
+            assert!(matches!(
+                Parser::new(program).parse().unwrap().eval(),
+                Err(EvalError::DivisionByZero { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn power_is_right_associative_and_binds_tighter_than_negation() {
+        assert_eq!(eval_infix("2 ^ 3 ^ 2"), Ok(Value::Int(int(512))));
+        assert_eq!(eval_infix("-2 ^ 2"), Ok(Value::Int(int(-4))));
+        assert_eq!(eval_infix("-2.5 ^ 2"), Ok(Value::Float(-6.25)));
+        assert_eq!(eval_infix("-x ^ 2"), eval_infix("-(x ^ 2)"));
+        assert_eq!(eval_infix("let x = 2 in -x ^ 2"), eval_infix("-2 ^ 2"));
+        // A negative literal is only split up as the base of '^'
+        assert_eq!(parse_infix("-2 * 3"), parse_infix("(-2) * 3"));
+        assert_eq!(parse_infix("-2 ^ 2"), parse_infix("-(2 ^ 2)"));
+        assert_eq!(
+            parse_infix("-x ^ 2"),
+            Expression::UnaryOp {
+                op: UnaryOperator::Negate,
+                child: Box::new(Expression::BinaryOp {
+                    op: BinaryOperator::Power,
+                    lhs: Box::new(Expression::Variable("x".to_string())),
+                    rhs: Box::new(Expression::Integer(int(2))),
+                }),
+            }
+        );
+        assert_eq!(eval_infix("2 * 3 ^ 2"), Ok(Value::Int(int(18))));
+        assert_eq!(eval_infix("pow(3, 4)"), Ok(Value::Int(int(81))));
+        assert_eq!(eval_infix("0 ^ 0"), Ok(Value::Int(int(1))));
+    }
+
+    #[test]
+    #[cfg(not(feature = "bigint"))]
+    fn power_overflow_follows_arithmetic_mode() {
+        let expr = parse_infix("2 ^ 64");
+        assert_eq!(
+            expr.eval(),
+            Err(EvalError::Overflow {
+                op: Operator::Binary(BinaryOperator::Power),
+                expr: expr.clone(),
+                span: None,
+            })
+        );
+        assert_eq!(
+            expr.eval_with_arithmetic(Arithmetic::Wrapping),
+            Ok(Value::Int(int(0)))
+        );
+        assert_eq!(
+            parse_infix("(-3) ^ 41").eval_with_arithmetic(Arithmetic::Saturating),
+            Ok(Value::Int(int(i64::MIN)))
+        );
+        assert_eq!(eval_infix("(-2) ^ 63"), Ok(Value::Int(int(i64::MIN))));
+        assert_eq!(
+            eval_infix("(-1) ^ 9223372036854775807"),
+            Ok(Value::Int(int(-1)))
+        );
+    }
+
+    #[test]
+    fn negative_exponent() {
+        let expr = parse_infix("2 ^ -1");
+        assert_eq!(
+            expr.eval(),
+            Err(EvalError::NegativeExponent {
+                expr: expr.clone(),
+                span: None,
+            })
+        );
+        assert_eq!(
+            expr.eval().unwrap_err().to_string(),
+            "negative exponent in '2 ^ (-1)'"
+        );
+    }
+
+    #[test]
+    fn builtins() {
+        let cases = [
+            ("abs(-5)", 5),
+            ("abs(5)", 5),
+            ("min(3, -2)", -2),
+            ("max(3, -2)", 3),
+            ("gcd(12, -18)", 6),
+            ("gcd(0, 0)", 0),
+            ("clamp(15, 0, 10)", 10),
+            ("clamp(-5, 0, 10)", 0),
+            ("clamp(5, 0, 10)", 5),
+            ("max(1, 2) + min(3, abs(-4)) * 2", 8),
+        ];
+        for (program, expected) in cases {
+            assert_eq!(
+                eval_infix(program),
+                Ok(Value::Int(int(expected))),
+                "{}",
+                program
+            );
+        }
+        assert_eq!(
+            Parser::new("max(+(1, 2), 0)").parse().unwrap().eval(),
+            Ok(Value::Int(int(3)))
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "bigint"))]
+    fn builtin_overflow() {
+        assert!(matches!(
+            eval_infix("abs(-9223372036854775808)"),
+            Err(EvalError::Overflow {
+                op: Operator::Builtin(Builtin::Abs),
+                ..
+            })
+        ));
+        assert_eq!(
+            Evaluator::new()
+                .with_arithmetic(Arithmetic::Saturating)
+                .eval(&parse_infix("gcd(-9223372036854775808, 0)")),
+            Ok(Value::Int(int(i64::MAX)))
+        );
+    }
+
+    #[test]
+    fn builtin_type_mismatch() {
+        assert_eq!(
+            eval_infix("max(1, T)"),
+            Err(EvalError::TypeMismatch {
+                op: Operator::Builtin(Builtin::Max),
+                expected: Type::Integer,
+                found: Type::Boolean,
+                expr: Expression::Boolean(true),
+                span: None,
+            })
+        );
+    }
+
+    #[test]
+    fn builtin_arity_is_checked_by_the_parser() {
+        let error = Parser::new("max(1)").parse().unwrap_err();
+        assert!(matches!(
+            error,
+            ParseError::UnexpectedToken { ref expected, .. } if expected == &vec![Expected::Comma]
+        ));
+        assert_eq!(
+            Parser::new("abs(1, 2)").parse().unwrap_err().to_string(),
+            "1:6: expected ')', found ','"
+        );
+    }
+
+    #[test]
+    fn builtin_names_are_still_variables() {
+        assert_eq!(eval_infix("let max = 3 in max + 1"), Ok(Value::Int(int(4))));
+    }
+
+    #[test]
+    fn display_math() {
+        let cases = [
+            ("(2 ^ 3) ^ 2", "(2 ^ 3) ^ 2", "^(^(2, 3), 2)"),
+            ("2 ^ 3 ^ 2", "2 ^ 3 ^ 2", "^(2, ^(3, 2))"),
+            ("(-x) ^ 2", "(-x) ^ 2", "^(-(x), 2)"),
+            ("(-2) ^ 2", "(-2) ^ 2", "^(-2, 2)"),
+            ("-2 ^ 2", "-2 ^ 2", "-(^(2, 2))"),
+            ("-2 ^ 3 ^ 2", "-2 ^ 3 ^ 2", "-(^(2, ^(3, 2)))"),
+            ("(-(1)) ^ x", "(-(1)) ^ x", "^(-(1), x)"),
+            ("a % b * c", "a % b * c", "*(%(a, b), c)"),
+            (
+                "clamp(x, 0, 1 + 2)",
+                "clamp(x, 0, 1 + 2)",
+                "clamp(x, 0, +(1, 2))",
+            ),
+        ];
+        for (program, infix, canonical) in cases {
+            let expr = parse_infix(program);
+            assert_eq!(format!("{}", expr), infix);
+            assert_eq!(format!("{}", expr.canonical()), canonical);
+            assert_eq!(parse_infix(infix), expr);
+            assert_eq!(Parser::new(canonical).parse(), Ok(expr));
+        }
+    }
+}
+
+#[cfg(all(test, feature = "bigint"))]
+mod bigint_tests {
+    use super::helpers::eval_infix;
+    use crate::error::{ConversionError, EvalError, Type};
+    use crate::evaluator::Arithmetic;
+    use crate::expression::{BinaryOperator, Expression};
+    use crate::integer::{int, Int};
+    use crate::parser::{lex, LexItem, Parser};
+    use crate::value::Value;
+
+    fn big(digits: &str) -> Int {
+        digits.parse().unwrap()
+    }
+
+    #[test]
+    fn lex_literals_of_any_length() {
+        assert_eq!(
+            lex("99999999999999999999, -123456789012345678901234567890"),
+            Ok(vec![
+                LexItem::Integer(big("99999999999999999999")),
+                LexItem::Comma,
+                LexItem::Integer(big("-123456789012345678901234567890")),
+            ])
+        );
+    }
+
+    #[test]
+    fn arithmetic_does_not_overflow() {
+        assert_eq!(
+            eval_infix("9223372036854775807 + 1"),
+            Ok(Value::Int(big("9223372036854775808")))
+        );
+        assert_eq!(
+            eval_infix("-(-9223372036854775808)"),
+            Ok(Value::Int(big("9223372036854775808")))
+        );
+        assert_eq!(
+            eval_infix("99999999999999999999 * 99999999999999999999"),
+            Ok(Value::Int(big("9999999999999999999800000000000000000001")))
+        );
+        assert_eq!(
+            eval_infix("2 ^ 100"),
+            Ok(Value::Int(big("1267650600228229401496703205376")))
+        );
+        assert_eq!(
+            eval_infix(
+                "let rec f = func n => if n < 1 then 1 else n * apply(f, n - 1) in apply(f, 25)"
+            ),
+            Ok(Value::Int(big("15511210043330985984000000")))
+        );
+    }
+
+    #[test]
+    fn huge_powers_overflow() {
+        for program in ["^(2, 4000000000)", "pow(-3, 1000000)", "^(2, 524289)"] {
+            let expr = Parser::new(program).parse().unwrap();
+            for arithmetic in [Arithmetic::Checked, Arithmetic::Saturating] {
+                assert!(
+                    matches!(
+                        expr.eval_with_arithmetic(arithmetic),
+                        Err(EvalError::Overflow { .. })
+                    ),
+                    "{}",
+                    program
+                );
+            }
+        }
+        assert_eq!(
+            eval_infix("2 ^ 524288").unwrap().as_int().unwrap().bits(),
+            524289
+        );
+        // Powers of 0, 1 and -1 stay small however large the exponent
+        assert_eq!(eval_infix("1 ^ 4000000000"), Ok(Value::Int(int(1))));
+        assert_eq!(eval_infix("(-1) ^ 4000000001"), Ok(Value::Int(int(-1))));
+        assert_eq!(eval_infix("0 ^ 4000000000"), Ok(Value::Int(int(0))));
+    }
+
+    #[test]
+    fn division_and_remainder_match_i64() {
+        let cases = [
+            ("-7 / 2", -3),
+            ("-7 % 2", -1),
+            ("7 % -2", 1),
+            ("mod(-7, 3)", 2),
+            ("mod(-7, -3)", 2),
+            ("gcd(-12, 18)", 6),
+            ("abs(-5)", 5),
+        ];
+        for (program, expected) in cases {
+            assert_eq!(
+                eval_infix(program),
+                Ok(Value::Int(int(expected))),
+                "{}",
+                program
+            );
+        }
+        assert_eq!(
+            eval_infix("-100000000000000000000 / 3"),
+            Ok(Value::Int(big("-33333333333333333333")))
+        );
+    }
+
+    #[test]
+    fn every_mode_is_exact() {
+        let expr = Parser::new("*(9223372036854775807, 2)").parse().unwrap();
+        for arithmetic in [
+            Arithmetic::Checked,
+            Arithmetic::Wrapping,
+            Arithmetic::Saturating,
+        ] {
+            assert_eq!(
+                expr.eval_with_arithmetic(arithmetic),
+                Ok(Value::Int(big("18446744073709551614")))
+            );
+        }
+    }
+
+    #[test]
+    fn conversions_to_i64_check_the_range() {
+        let value = Value::Int(big("9223372036854775808"));
+        assert_eq!(value.as_i64(), None);
+        assert_eq!(
+            i64::try_from(value.clone()),
+            Err(ConversionError {
+                expected: Type::Integer,
+                value: value.clone(),
+            })
+        );
+        assert_eq!(Int::try_from(value), Ok(big("9223372036854775808")));
+        assert_eq!(Value::Int(int(7)).as_i64(), Some(7));
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let program = "+(123456789012345678901234567890, -1)";
+        let expr = Parser::new(program).parse().unwrap();
+        assert_eq!(
+            expr,
+            Expression::BinaryOp {
+                op: BinaryOperator::Add,
+                lhs: Box::new(Expression::Integer(big("123456789012345678901234567890"))),
+                rhs: Box::new(Expression::Integer(int(-1))),
+            }
+        );
+        assert_eq!(format!("{}", expr.canonical()), program);
+        assert_eq!(
+            expr.eval().unwrap().to_string(),
+            "123456789012345678901234567889"
+        );
+    }
+}
+
+#[cfg(test)]
+mod rational_tests {
+    use super::helpers::{eval_infix_with, Mode::Exact};
+    use crate::error::{EvalError, Type};
+    use crate::evaluator::{Division, Evaluator};
+    #[cfg(not(feature = "bigint"))]
+    use crate::expression::Builtin;
+    use crate::expression::{BinaryOperator, Expression, Operator};
+    use crate::integer::int;
+    use crate::parser::Parser;
+    use crate::rational::Rational;
+    use crate::value::{Bindings, Value};
+
+    fn ratio(numer: i64, denom: i64) -> Rational {
+        Rational::new(int(numer), int(denom)).unwrap()
+    }
+
+    #[test]
+    fn fractions_are_normalized() {
+        assert_eq!(ratio(6, -4), ratio(-3, 2));
+        assert_eq!(ratio(-3, 2).numer(), &int(-3));
+        assert_eq!(ratio(-3, 2).denom(), &int(2));
+        assert_eq!(ratio(0, -5), Rational::from(int(0)));
+        assert!(ratio(4, 2).is_integer());
+        assert_eq!(Rational::new(int(1), int(0)), None);
+    }
+
+    #[test]
+    fn division_is_truncating_by_default() {
+        let expr = Parser::new("/(7, 2)").parse().unwrap();
+        assert_eq!(expr.eval(), Ok(Value::Int(int(3))));
+        assert_eq!(
+            expr.eval_with_division(Division::Exact),
+            Ok(Value::Rational(ratio(7, 2)))
+        );
+    }
+
+    #[test]
+    fn exact_division() {
+        assert_eq!(eval_infix_with("8 / 2", Exact), Ok(Value::Int(int(4))));
+        assert_eq!(
+            eval_infix_with("-7 / 2", Exact),
+            Ok(Value::Rational(ratio(-7, 2)))
+        );
+        assert_eq!(
+            eval_infix_with("1 / 3 + 1 / 6", Exact),
+            Ok(Value::Rational(ratio(1, 2)))
+        );
+        assert_eq!(eval_infix_with("1 / 3 * 3", Exact), Ok(Value::Int(int(1))));
+        assert_eq!(
+            eval_infix_with("2 - 1 / 4", Exact),
+            Ok(Value::Rational(ratio(7, 4)))
+        );
+        assert_eq!(
+            eval_infix_with("(1 / 2) / (3 / 4)", Exact),
+            Ok(Value::Rational(ratio(2, 3)))
+        );
+        assert_eq!(
+            eval_infix_with("-(5 / 3)", Exact),
+            Ok(Value::Rational(ratio(-5, 3)))
+        );
+        assert_eq!(eval_infix_with("7 - 7", Exact), Ok(Value::Int(int(0))));
+    }
+
+    #[test]
+    fn exact_division_by_zero() {
+        for program in ["1 / 0", "(1 / 2) / 0", "1 / (1 / 2 - 1 / 2)"] {
+            assert!(
+                matches!(
+                    eval_infix_with(program, Exact),
+                    Err(EvalError::DivisionByZero { .. })
+                ),
+                "{}",
+                program
+            );
+        }
+    }
+
+    #[test]
+    fn comparisons_mix_fractions_and_integers() {
+        let cases = [
+            ("1 / 3 < 1 / 2", true),
+            ("7 / 2 > 3", true),
+            ("7 / 2 <= 4", true),
+            ("-1 / 2 >= 0", false),
+            ("2 / 4 = 1 / 2", true),
+            ("4 / 2 = 2", true),
+            ("1 / 2 != 1", true),
+        ];
+        for (program, expected) in cases {
+            assert_eq!(
+                eval_infix_with(program, Exact),
+                Ok(Value::Bool(expected)),
+                "{}",
+                program
+            );
+        }
+    }
+
+    #[test]
+    fn fractions_flow_through_functions_and_bindings() {
+        let expr = Parser::new("apply(func x => +(x, x), /(price, 4))")
+            .parse()
+            .unwrap();
+        let bindings = Bindings::new().with("price", 5);
+        let evaluator = Evaluator::new().with_division(Division::Exact);
+        assert_eq!(
+            evaluator.eval_with(&expr, &bindings),
+            Ok(Value::Rational(ratio(5, 2)))
+        );
+
+        // A fraction passed in makes the arithmetic exact, whatever the division
+        let expr = Parser::new("*(x, 2)").parse().unwrap();
+        let bindings = Bindings::new().with("x", Value::Rational(ratio(1, 3)));
+        assert_eq!(expr.eval_with(&bindings), Ok(Value::Rational(ratio(2, 3))));
+    }
+
+    #[test]
+    fn fractions_are_not_integers() {
+        assert_eq!(
+            eval_infix_with("(1 / 2) % 2", Exact),
+            Err(EvalError::TypeMismatch {
+                op: Operator::Binary(BinaryOperator::Remainder),
+                expected: Type::Integer,
+                found: Type::Rational,
+                expr: Parser::new("/(1, 2)").parse().unwrap(),
+                span: None,
+            })
+        );
+        assert!(matches!(
+            eval_infix_with("1 / 2 + T", Exact),
+            Err(EvalError::TypeMismatch {
+                found: Type::Boolean,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn ordering_builtins_take_fractions() {
+        assert_eq!(
+            eval_infix_with("abs(-1 / 2)", Exact),
+            Ok(Value::Rational(ratio(1, 2)))
+        );
+        assert_eq!(
+            eval_infix_with("min(1 / 3, 1 / 4)", Exact),
+            Ok(Value::Rational(ratio(1, 4)))
+        );
+        assert_eq!(
+            eval_infix_with("max(1 / 3, 1)", Exact),
+            Ok(Value::Int(int(1)))
+        );
+        assert_eq!(
+            eval_infix_with("clamp(5 / 2, 0, 1 / 2)", Exact),
+            Ok(Value::Rational(ratio(1, 2)))
+        );
+        assert_eq!(
+            eval_infix_with("clamp(-5 / 2, 0, 1)", Exact),
+            Ok(Value::Int(int(0)))
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "bigint"))]
+    fn fraction_overflow() {
+        assert!(matches!(
+            eval_infix_with("1 / 9223372036854775807 + 1 / 9223372036854775806", Exact),
+            Err(EvalError::Overflow {
+                op: Operator::Binary(BinaryOperator::Add),
+                ..
+            })
+        ));
+        assert!(matches!(
+            eval_infix_with(
+                "max(2 / 9223372036854775807, 5 / 9223372036854775806)",
+                Exact
+            ),
+            Err(EvalError::Overflow {
+                op: Operator::Builtin(Builtin::Max),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn display_fraction() {
+        assert_eq!(ratio(7, 2).to_string(), "7/2");
+        assert_eq!(ratio(-7, 2).to_string(), "-7/2");
+        assert_eq!(Value::Rational(ratio(1, 3)).to_string(), "1/3");
+        assert_eq!(Rational::from(int(5)).to_string(), "5");
+    }
+
+    #[test]
+    fn display_fraction_as_decimal() {
+        let cases = [
+            (ratio(7, 2), 2, "3.50"),
+            (ratio(1, 3), 4, "0.3333"),
+            (ratio(2, 3), 2, "0.67"),
+            (ratio(-2, 3), 2, "-0.67"),
+            (ratio(5, 8), 2, "0.63"),
+            (ratio(-5, 8), 2, "-0.63"),
+            (ratio(999, 1000), 2, "1.00"),
+            (ratio(-1, 1000), 2, "0.00"),
+            (ratio(7, 2), 0, "4"),
+            (ratio(-1, 3), 0, "0"),
+            (ratio(19, 2), 0, "10"),
+            (ratio(1, 9223372036854775807), 3, "0.000"),
+            (ratio(9223372036854775806, 9223372036854775807), 3, "1.000"),
+        ];
+        for (value, places, expected) in cases {
+            assert_eq!(value.to_decimal(places), expected, "{:?}", value);
+        }
+        assert_eq!(format!("{:.2}", Value::Rational(ratio(22, 7))), "3.14");
+        assert_eq!(format!("{:.2}", Value::Int(int(3))), "3.00");
+        assert_eq!(format!("{:.1}", ratio(1, 4)), "0.3");
+    }
+
+    #[test]
+    fn fraction_conversions() {
+        let value = Value::Rational(ratio(3, 4));
+        assert_eq!(value.type_of(), Type::Rational);
+        assert_eq!(value.as_rational(), Some(&ratio(3, 4)));
+        assert_eq!(value.as_i64(), None);
+        assert_eq!(Rational::try_from(value.clone()), Ok(ratio(3, 4)));
+        assert_eq!(Rational::try_from(Value::Int(int(2))), Ok(ratio(2, 1)));
+        assert_eq!(Value::from(ratio(6, 3)), Value::Int(int(2)));
+        assert!(Rational::try_from(Value::Bool(true)).is_err());
+        assert_eq!(
+            value.to_expression(),
+            Expression::BinaryOp {
+                op: BinaryOperator::Divide,
+                lhs: Box::new(Expression::Integer(int(3))),
+                rhs: Box::new(Expression::Integer(int(4))),
+            }
+        );
+    }
+}
+
+#[cfg(test)]
+mod float_tests {
+    use std::str::FromStr;
+
+    use rust_decimal::Decimal;
+
+    use super::helpers::{eval_infix, eval_infix_with, parse_infix, Mode};
+    use crate::error::{EvalError, LexError, Type};
+    use crate::evaluator::{Division, Evaluator, Floats};
+    use crate::expression::{BinaryOperator, Expression, Operator, UnaryOperator};
+    use crate::integer::int;
+    use crate::parser::{lex, LexItem, Parser};
+    use crate::rational::Rational;
+    use crate::span::{Position, Span};
+    use crate::value::{Bindings, Value};
+
+    fn decimal(literal: &str) -> Value {
+        Value::Decimal(Decimal::from_str(literal).unwrap())
+    }
+
+    fn float_item(value: f64, text: &str) -> LexItem {
+        LexItem::Float {
+            value,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn lex_floats() {
+        assert_eq!(
+            lex("3.25, -0.5, 1e3, 6.02E+23, 2.5e-3"),
+            Ok(vec![
+                float_item(3.25, "3.25"),
+                LexItem::Comma,
+                float_item(-0.5, "-0.5"),
+                LexItem::Comma,
+                float_item(1000.0, "1e3"),
+                LexItem::Comma,
+                float_item(6.02e23, "6.02E+23"),
+                LexItem::Comma,
+                float_item(0.0025, "2.5e-3"),
+            ])
+        );
+        assert_eq!(
+            lex("1.5-2.5"),
+            Ok(vec![
+                float_item(1.5, "1.5"),
+                LexItem::BinaryOp(BinaryOperator::Subtract),
+                float_item(2.5, "2.5"),
+            ])
+        );
+    }
+
+    #[test]
+    fn lex_point_and_exponent_need_digits() {
+        assert_eq!(
+            lex("2e"),
+            Ok(vec![
+                LexItem::Integer(int(2)),
+                LexItem::Variable("e".to_string()),
+            ])
+        );
+        assert_eq!(
+            lex("2ex"),
+            Ok(vec![
+                LexItem::Integer(int(2)),
+                LexItem::Variable("ex".to_string()),
+            ])
+        );
+        assert!(matches!(
+            lex("3."),
+            Err(LexError::UnexpectedCharacter { found: '.', .. })
+        ));
+    }
+
+    #[test]
+    fn lex_float_out_of_range() {
+        assert_eq!(
+            lex("1e400"),
+            Err(LexError::FloatOutOfRange {
+                literal: "1e400".to_string(),
+                span: Span::new(0, 5),
+                position: Position { line: 1, column: 1 },
+            })
+        );
+        assert_eq!(
+            lex("1e400").unwrap_err().to_string(),
+            "1:1: float literal 1e400 is out of range"
+        );
+    }
+
+    #[test]
+    fn parse_float() {
+        assert_eq!(
+            Parser::new("*(2.5, x)").parse(),
+            Ok(Expression::BinaryOp {
+                op: BinaryOperator::Multiply,
+                lhs: Box::new(Expression::float(2.5)),
+                rhs: Box::new(Expression::Variable("x".to_string())),
+            })
+        );
+    }
+
+    #[test]
+    fn display_float() {
+        let cases = [
+            ("2.0", "2.0", "2.0"),
+            ("-(2.5)", "-(2.5)", "-(2.5)"),
+            ("(-2.5) ^ 2", "(-2.5) ^ 2", "^(-2.5, 2)"),
+            ("1e100 * 0.001", "1e100 * 0.001", "*(1e100, 0.001)"),
+        ];
+        for (program, infix, canonical) in cases {
+            let expr = parse_infix(program);
+            assert_eq!(format!("{}", expr), infix);
+            assert_eq!(format!("{}", expr.canonical()), canonical);
+            assert_eq!(Parser::new(canonical).parse(), Ok(expr));
+        }
+    }
+
+    #[test]
+    fn float_arithmetic() {
+        assert_eq!(eval_infix("1.5 + 2.25"), Ok(Value::Float(3.75)));
+        assert_eq!(eval_infix("7.0 / 2"), Ok(Value::Float(3.5)));
+        assert_eq!(eval_infix("-(0.5) * 4"), Ok(Value::Float(-2.0)));
+        assert_eq!(eval_infix("7.5 % 2"), Ok(Value::Float(1.5)));
+        assert_eq!(eval_infix("2 ^ 0.5"), Ok(Value::Float(2f64.sqrt())));
+        assert_eq!(eval_infix("2 ^ -1.0"), Ok(Value::Float(0.5)));
+        assert_eq!(eval_infix("0.1 + 0.2"), Ok(Value::Float(0.1 + 0.2)));
+    }
+
+    #[test]
+    fn integers_and_fractions_promote_to_floats() {
+        assert_eq!(eval_infix("1 + 0.5"), Ok(Value::Float(1.5)));
+        assert_eq!(eval_infix("0.5 * 3"), Ok(Value::Float(1.5)));
+        assert_eq!(
+            eval_infix_with("1 / 4 + 0.5", Mode::Exact),
+            Ok(Value::Float(0.75))
+        );
+        // Integer operations stay integers
+        assert_eq!(eval_infix("7 / 2"), Ok(Value::Int(int(3))));
+    }
+
+    #[test]
+    fn float_division_by_zero_is_not_an_error() {
+        assert_eq!(eval_infix("1.0 / 0"), Ok(Value::Float(f64::INFINITY)));
+        assert_eq!(eval_infix("-1 / 0.0"), Ok(Value::Float(f64::NEG_INFINITY)));
+        assert!(matches!(
+            eval_infix("0.0 / 0"),
+            Ok(Value::Float(value)) if value.is_nan()
+        ));
+        assert!(matches!(
+            eval_infix("1 / 0"),
+            Err(EvalError::DivisionByZero { .. })
+        ));
+    }
+
+    #[test]
+    fn non_finite_floats_round_trip() {
+        let nan = eval_infix("let n = 0.0 / 0.0 in func x => x + n").unwrap();
+        let expr = nan.to_expression();
+        assert_eq!(expr.to_string(), "func x => x + 0.0 / 0.0");
+        assert_eq!(expr.canonical().to_string(), "func x => +(x, /(0.0, 0.0))");
+        assert_eq!(parse_infix(&expr.to_string()), expr);
+        for value in [f64::INFINITY, f64::NEG_INFINITY] {
+            let expr = Value::Float(value).to_expression();
+            assert_eq!(
+                Parser::new(&expr.canonical().to_string()).parse(),
+                Ok(expr.clone())
+            );
+            assert_eq!(expr.eval(), Ok(Value::Float(value)));
+        }
+        assert_eq!(
+            Expression::float(f64::NEG_INFINITY).to_string(),
+            "-1.0 / 0.0"
+        );
+        assert!(matches!(
+            Expression::float(f64::NAN).eval(),
+            Ok(Value::Float(value)) if value.is_nan()
+        ));
+    }
+
+    #[test]
+    fn comparisons_with_nan_and_infinity() {
+        let bindings = Bindings::new()
+            .with("nan", f64::NAN)
+            .with("inf", f64::INFINITY);
+        let cases = [
+            ("nan < 1", false),
+            ("nan > 1", false),
+            ("nan <= nan", false),
+            ("nan >= 1.0", false),
+            ("nan = nan", false),
+            ("nan != nan", true),
+            ("nan = 1", false),
+            ("inf > 9223372036854775807", true),
+            ("-inf < -1e300", true),
+            ("inf = inf", true),
+            ("inf >= inf", true),
+            ("inf - inf = inf - inf", false),
+        ];
+        for (program, expected) in cases {
+            let expr = parse_infix(program);
+            assert_eq!(
+                expr.eval_with(&bindings),
+                Ok(Value::Bool(expected)),
+                "{}",
+                program
+            );
+        }
+    }
+
+    #[test]
+    fn floats_equal_numbers_of_other_kinds() {
+        let cases = [
+            ("2.0 = 2", true),
+            ("2 = 2.0", true),
+            ("0.5 != 1", true),
+            ("-0.0 = 0", true),
+            ("1.5 < 2", true),
+            ("2.5 >= 3", false),
+        ];
+        for (program, expected) in cases {
+            assert_eq!(
+                eval_infix(program),
+                Ok(Value::Bool(expected)),
+                "{}",
+                program
+            );
+        }
+        assert_eq!(
+            eval_infix_with("1 / 2 = 0.5", Mode::Exact),
+            Ok(Value::Bool(true))
+        );
+        // Other values of different types are still never equal
+        assert_eq!(eval_infix("1.0 = T"), Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn whole_floats_compare_exactly_with_integers() {
+        // 2^53 + 1 is the first integer that is not a float
+        let cases = [
+            ("=(9007199254740993, 9007199254740992.0)", false),
+            ("=(9007199254740993, 9007199254740992)", false),
+            ("!=(9007199254740992.0, 9007199254740993)", true),
+            ("<(9007199254740992.0, 9007199254740993)", true),
+            (">=(9007199254740992.0, 9007199254740993)", false),
+            ("=(9007199254740992, 9007199254740992.0)", true),
+            ("<(9223372036854775807, 9223372036854775808.0)", true),
+            ("=(-9223372036854775808, -9223372036854775808.0)", true),
+            (">(-9223372036854775807, -1e300)", true),
+        ];
+        for (program, expected) in cases {
+            assert_eq!(
+                Parser::new(program).parse().unwrap().eval(),
+                Ok(Value::Bool(expected)),
+                "{}",
+                program
+            );
+        }
+        assert_eq!(
+            eval_infix_with("9007199254740993 / 2 > 4503599627370496.0", Mode::Exact),
+            Ok(Value::Bool(true))
+        );
+    }
+
+    #[test]
+    fn float_type_mismatch() {
+        assert_eq!(
+            eval_infix("1.5 + T"),
+            Err(EvalError::TypeMismatch {
+                op: Operator::Binary(BinaryOperator::Add),
+                expected: Type::Float,
+                found: Type::Boolean,
+                expr: Expression::Boolean(true),
+                span: None,
+            })
+        );
+        assert!(matches!(
+            eval_infix("gcd(1.5, 2)"),
+            Err(EvalError::TypeMismatch {
+                expected: Type::Integer,
+                found: Type::Float,
+                ..
+            })
+        ));
+        assert!(matches!(
+            eval_infix("if 1.0 then 1 else 2"),
+            Err(EvalError::NonBooleanCondition {
+                found: Type::Float,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn ordering_builtins_take_floats_and_decimals() {
+        assert_eq!(eval_infix("abs(-1.5)"), Ok(Value::Float(1.5)));
+        assert_eq!(eval_infix("min(2, 1.5)"), Ok(Value::Float(1.5)));
+        assert_eq!(eval_infix("max(2, 1.5)"), Ok(Value::Float(2.0)));
+        assert_eq!(eval_infix("clamp(0.5, 1, 2)"), Ok(Value::Float(1.0)));
+        assert_eq!(eval_infix("clamp(1.5, 2, 1)"), Ok(Value::Float(2.0)));
+        assert!(matches!(
+            eval_infix("max(0.0 / 0.0, 1)"),
+            Ok(Value::Float(value)) if value.is_nan()
+        ));
+        assert_eq!(
+            eval_infix_with("abs(-2.50)", Mode::Decimal),
+            Ok(decimal("2.50"))
+        );
+        assert_eq!(
+            eval_infix_with("min(0.1, 1)", Mode::Decimal),
+            Ok(decimal("0.1"))
+        );
+        assert_eq!(
+            eval_infix_with("clamp(3, 0.5, 2.25)", Mode::Decimal),
+            Ok(decimal("2.25"))
+        );
+        assert!(matches!(
+            eval_infix("max(1.5, T)"),
+            Err(EvalError::TypeMismatch {
+                expected: Type::Float,
+                found: Type::Boolean,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn decimal_literals_are_exact() {
+        assert_eq!(
+            eval_infix_with("0.1 + 0.2", Mode::Decimal),
+            Ok(decimal("0.3"))
+        );
+        assert_eq!(
+            eval_infix_with("0.1 + 0.2 = 0.3", Mode::Decimal),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(eval_infix("0.1 + 0.2 = 0.3"), Ok(Value::Bool(false)));
+        assert_eq!(
+            eval_infix_with("19.99 * 3", Mode::Decimal),
+            Ok(decimal("59.97"))
+        );
+        assert_eq!(
+            eval_infix_with("100.00 - 0.01", Mode::Decimal),
+            Ok(decimal("99.99"))
+        );
+        assert_eq!(
+            eval_infix_with("-(2.50)", Mode::Decimal),
+            Ok(decimal("-2.50"))
+        );
+        assert_eq!(eval_infix_with("1e-2", Mode::Decimal), Ok(decimal("0.01")));
+        assert_eq!(
+            eval_infix_with("1.05 ^ 2", Mode::Decimal),
+            Ok(decimal("1.1025"))
+        );
+        assert_eq!(
+            eval_infix_with("7.5 % 2", Mode::Decimal),
+            Ok(decimal("1.5"))
+        );
+        assert_eq!(
+            eval_infix_with("2.50e2", Mode::Decimal),
+            Ok(decimal("250.0"))
+        );
+        // Integer-only expressions are unaffected
+        assert_eq!(
+            eval_infix_with("7 / 2", Mode::Decimal),
+            Ok(Value::Int(int(3)))
+        );
+    }
+
+    #[test]
+    fn decimal_literals_are_read_from_their_text() {
+        // More significant digits than an f64 holds
+        assert_eq!(
+            eval_infix_with("12345678901234567.89", Mode::Decimal),
+            Ok(decimal("12345678901234567.89"))
+        );
+        assert_eq!(
+            eval_infix_with("0.1234567890123456789 * 10", Mode::Decimal),
+            Ok(decimal("1.2345678901234567890"))
+        );
+        assert_eq!(
+            eval_infix_with("-12345678901234567.89 ^ 1", Mode::Decimal),
+            Ok(decimal("-12345678901234567.89"))
+        );
+        assert_eq!(
+            eval_infix_with("12345678901234567.89", Mode::Decimal)
+                .unwrap()
+                .to_expression(),
+            parse_infix("12345678901234567.89")
+        );
+        // Too many digits for a decimal is an error rather than a rounding
+        assert!(matches!(
+            eval_infix_with("0.12345678901234567890123456789", Mode::Decimal),
+            Err(EvalError::DecimalOutOfRange { .. })
+        ));
+        assert!(matches!(
+            eval_infix_with("1e-29", Mode::Decimal),
+            Err(EvalError::DecimalOutOfRange { .. })
+        ));
+        assert_eq!(
+            eval_infix_with("0.0e-40", Mode::Decimal),
+            Ok(decimal("0.0"))
+        );
+    }
+
+    #[test]
+    fn decimal_powers() {
+        assert_eq!(
+            eval_infix_with("(-2.0) ^ 3", Mode::Decimal),
+            Ok(decimal("-8"))
+        );
+        assert_eq!(
+            eval_infix_with("2.0 ^ -3", Mode::Decimal),
+            Ok(decimal("0.125"))
+        );
+        assert_eq!(eval_infix_with("0.0 ^ 0", Mode::Decimal), Ok(decimal("1")));
+        assert!(matches!(
+            eval_infix_with("0.0 ^ -1", Mode::Decimal),
+            Err(EvalError::DivisionByZero { .. })
+        ));
+        for program in ["(-4.0) ^ 0.5", "(-1.0) ^ 0.5", "(-8.0) ^ 0.333"] {
+            assert!(
+                matches!(
+                    eval_infix_with(program, Mode::Decimal),
+                    Err(EvalError::NegativeBase { .. })
+                ),
+                "{}",
+                program
+            );
+        }
+        assert_eq!(
+            eval_infix_with("(-4.0) ^ 0.5", Mode::Decimal)
+                .unwrap_err()
+                .to_string(),
+            "negative base with a fractional exponent in '(-4.0) ^ 0.5'"
+        );
+        // Results too close to zero for a decimal round to zero
+        assert_eq!(
+            eval_infix_with("0.5 ^ 99999999999999", Mode::Decimal),
+            Ok(decimal("0"))
+        );
+        assert_eq!(
+            eval_infix_with("10.0 ^ -30", Mode::Decimal),
+            Ok(decimal("0"))
+        );
+        assert!(matches!(
+            eval_infix_with("100.0 ^ 100", Mode::Decimal),
+            Err(EvalError::Overflow {
+                op: Operator::Binary(BinaryOperator::Power),
+                ..
+            })
+        ));
+        assert!(matches!(
+            eval_infix_with("0.5 ^ -1000", Mode::Decimal),
+            Err(EvalError::Overflow { .. })
+        ));
+    }
+
+    #[test]
+    fn decimal_division() {
+        assert_eq!(
+            eval_infix_with("10.00 / 4", Mode::Decimal),
+            Ok(decimal("2.5"))
+        );
+        assert_eq!(
+            eval_infix_with("1.0 / 3", Mode::Decimal),
+            Ok(decimal("0.3333333333333333333333333333"))
+        );
+        assert!(matches!(
+            eval_infix_with("1.5 / 0", Mode::Decimal),
+            Err(EvalError::DivisionByZero { .. })
+        ));
+        assert!(matches!(
+            eval_infix_with("1.5 % 0.0", Mode::Decimal),
+            Err(EvalError::DivisionByZero { .. })
+        ));
+    }
+
+    #[test]
+    fn decimal_comparisons_mix_with_integers_and_fractions() {
+        let exact = Evaluator::new()
+            .with_floats(Floats::Decimal)
+            .with_division(Division::Exact);
+        let cases = [
+            ("2.50 = 2.5", true),
+            ("2.00 = 2", true),
+            ("0.5 = 1 / 2", true),
+            ("0.33 < 1 / 3", true),
+            ("1 / 2 + 0.25", false),
+        ];
+        for (program, expected) in &cases[..4] {
+            assert_eq!(
+                exact.eval(&parse_infix(program)),
+                Ok(Value::Bool(*expected)),
+                "{}",
+                program
+            );
+        }
+        assert_eq!(exact.eval(&parse_infix(cases[4].0)), Ok(decimal("0.75")));
+    }
+
+    #[test]
+    fn decimals_and_floats_promote_to_floats() {
+        let bindings = Bindings::new().with("x", 0.5);
+        let expr = parse_infix("x + 1.25");
+        assert_eq!(
+            Evaluator::new()
+                .with_floats(Floats::Decimal)
+                .eval_with(&expr, &bindings),
+            Ok(Value::Float(1.75))
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "bigint"))]
+    fn decimal_overflow() {
+        assert_eq!(
+            eval_infix_with("79228162514264337593543950336.0 * 2", Mode::Decimal),
+            Err(EvalError::DecimalOutOfRange {
+                expr: Expression::Float {
+                    value: 79228162514264337593543950336.0,
+                    text: "79228162514264337593543950336.0".to_string(),
+                },
+                span: None,
+            })
+        );
+        let expr = parse_infix("70000000000000000000000000000.0 * 70000000000000000000000000000.0");
+        assert!(matches!(
+            expr.eval_with_floats(Floats::Decimal),
+            Err(EvalError::Overflow {
+                op: Operator::Binary(BinaryOperator::Multiply),
+                ..
+            })
+        ));
+        assert!(matches!(
+            eval_infix_with(
+                "9.0 * 9223372036854775807 * 9223372036854775807",
+                Mode::Decimal
+            ),
+            Err(EvalError::Overflow {
+                op: Operator::Binary(BinaryOperator::Multiply),
+                ..
+            })
+        ));
+        assert_eq!(
+            eval_infix_with("1e28 * 10", Mode::Decimal)
+                .unwrap_err()
+                .to_string(),
+            "overflow in '*' evaluating '1e28 * 10'"
+        );
+    }
+
+    #[test]
+    fn display_float_and_decimal_values() {
+        assert_eq!(Value::Float(2.0).to_string(), "2.0");
+        assert_eq!(Value::Float(0.1 + 0.2).to_string(), "0.30000000000000004");
+        assert_eq!(Value::Float(f64::NAN).to_string(), "NaN");
+        assert_eq!(Value::Float(f64::NEG_INFINITY).to_string(), "-inf");
+        assert_eq!(format!("{:.2}", Value::Float(2.0 / 3.0)), "0.67");
+        assert_eq!(decimal("59.970").to_string(), "59.970");
+        assert_eq!(format!("{:.2}", decimal("2.345")), "2.35");
+        assert_eq!(format!("{:.3}", decimal("-2.5")), "-2.500");
+    }
+
+    #[test]
+    fn float_conversions() {
+        assert_eq!(Value::Float(1.5).type_of(), Type::Float);
+        assert_eq!(decimal("1.5").type_of(), Type::Decimal);
+        assert_eq!(Value::Float(1.5).as_f64(), Some(1.5));
+        assert_eq!(Value::Int(int(2)).as_f64(), None);
+        assert_eq!(Value::Int(int(2)).to_f64(), Some(2.0));
+        assert_eq!(
+            Value::Rational(Rational::new(int(1), int(4)).unwrap()).to_f64(),
+            Some(0.25)
+        );
+        assert_eq!(decimal("1.5").as_decimal(), Decimal::from_str("1.5").ok());
+        assert_eq!(f64::try_from(decimal("1.5")), Ok(1.5));
+        assert_eq!(f64::try_from(Value::Int(int(3))), Ok(3.0));
+        assert!(f64::try_from(Value::Bool(true)).is_err());
+        assert_eq!(Decimal::try_from(Value::Int(int(3))), Ok(Decimal::from(3)));
+        assert!(Decimal::try_from(Value::Float(1.5)).is_err());
+        assert_eq!(Value::from(0.5), Value::Float(0.5));
+        assert_eq!(Value::Float(0.5).to_expression(), Expression::float(0.5));
+        assert_eq!(
+            decimal("0.250").to_expression(),
+            Expression::Float {
+                value: 0.25,
+                text: "0.250".to_string(),
+            }
+        );
+        assert_eq!(
+            Value::Decimal(Decimal::from(3)).to_expression().to_string(),
+            "3.0"
+        );
+        assert_eq!(
+            Expression::UnaryOp {
+                op: UnaryOperator::Negate,
+                child: Box::new(Expression::float(0.5)),
+            }
+            .eval(),
+            Ok(Value::Float(-0.5))
+        );
+    }
+}
+
+#[cfg(test)]
+mod string_tests {
+    use super::helpers::{eval_infix, eval_infix_with, parse_infix, Mode};
+    use crate::error::{EvalError, LexError, Type};
+    use crate::evaluator::Evaluator;
+    use crate::expression::{BinaryOperator, Builtin, Expression, Operator};
+    use crate::integer::int;
+    use crate::parser::{lex, LexItem, Parser, Syntax};
+    use crate::span::{Position, Span};
+    use crate::value::{Bindings, Value};
+
+    fn string(text: &str) -> Value {
+        Value::String(text.to_string())
+    }
+
+    #[test]
+    fn lex_strings() {
+        assert_eq!(
+            lex(r#""abc" + """#),
+            Ok(vec![
+                LexItem::String("abc".to_string()),
+                LexItem::BinaryOp(BinaryOperator::Add),
+                LexItem::String(String::new()),
+            ])
+        );
+        assert_eq!(
+            lex(r#""say \"hi\"\n\tand \\ \0 \u{e9}\u{1F600}""#),
+            Ok(vec![LexItem::String(
+                "say \"hi\"\n\tand \\ \0 é😀".to_string()
+            )])
+        );
+        // Comment markers and keywords are just text inside a string
+        assert_eq!(
+            lex(r##""# not /* a */ comment if""##),
+            Ok(vec![LexItem::String(
+                "# not /* a */ comment if".to_string()
+            )])
+        );
+    }
+
+    #[test]
+    fn minus_after_a_string_is_subtraction() {
+        assert_eq!(
+            lex(r#""a"-1"#),
+            Ok(vec![
+                LexItem::String("a".to_string()),
+                LexItem::BinaryOp(BinaryOperator::Subtract),
+                LexItem::Integer(int(1)),
+            ])
+        );
+    }
+
+    #[test]
+    fn lex_unterminated_string() {
+        assert_eq!(
+            lex("1 + \"abc"),
+            Err(LexError::UnterminatedString {
+                span: Span::new(4, 8),
+                position: Position { line: 1, column: 5 },
+            })
+        );
+        assert_eq!(
+            lex(r#""abc\"#).unwrap_err().to_string(),
+            "1:1: unterminated string literal"
+        );
+    }
+
+    #[test]
+    fn lex_invalid_escapes() {
+        assert_eq!(
+            lex(r#""a\qb""#),
+            Err(LexError::InvalidEscape {
+                escape: r"\q".to_string(),
+                span: Span::new(2, 4),
+                position: Position { line: 1, column: 3 },
+            })
+        );
+        let invalid = [
+            r"\u",
+            r"\u{}",
+            r"\u{110000}",
+            r"\u{d800}",
+            r"\u{1234567}",
+            r"\u{41",
+        ];
+        for escape in invalid {
+            let program = format!("\"{}\"", escape);
+            assert!(
+                matches!(lex(&program), Err(LexError::InvalidEscape { .. })),
+                "{}",
+                program
+            );
+        }
+        assert_eq!(
+            lex(r#""\x41""#).unwrap_err().to_string(),
+            r"1:2: invalid escape '\x' in string literal"
+        );
+    }
+
+    #[test]
+    fn display_strings() {
+        let cases = [
+            (r#""abc""#, r#""abc""#, r#""abc""#),
+            (
+                r#""a" + "b\"c\\""#,
+                r#""a" + "b\"c\\""#,
+                r#"+("a", "b\"c\\")"#,
+            ),
+            (r#""line\n\u{7}""#, r#""line\n\u{7}""#, r#""line\n\u{7}""#),
+            (r#"len("é") = 1"#, r#"len("é") = 1"#, r#"=(len("é"), 1)"#),
+        ];
+        for (program, infix, canonical) in cases {
+            let expr = parse_infix(program);
+            assert_eq!(format!("{}", expr), infix);
+            assert_eq!(format!("{}", expr.canonical()), canonical);
+            assert_eq!(Parser::new(canonical).parse(), Ok(expr));
+        }
+    }
+
+    #[test]
+    fn concatenation() {
+        assert_eq!(eval_infix(r#""foo" + "bar""#), Ok(string("foobar")));
+        assert_eq!(eval_infix(r#""" + """#), Ok(string("")));
+        assert_eq!(
+            eval_infix(r#"let label = func n => "item " + to_string(n) in apply(label, 3)"#),
+            Ok(string("item 3"))
+        );
+        assert_eq!(
+            Parser::new(r#"+("a", +("b", "c"))"#)
+                .parse()
+                .unwrap()
+                .eval(),
+            Ok(string("abc"))
+        );
+    }
+
+    #[test]
+    fn concatenation_needs_two_strings() {
+        assert_eq!(
+            eval_infix(r#""total: " + 5"#),
+            Err(EvalError::TypeMismatch {
+                op: Operator::Binary(BinaryOperator::Add),
+                expected: Type::String,
+                found: Type::Integer,
+                expr: Expression::Integer(int(5)),
+                span: None,
+            })
+        );
+        assert_eq!(
+            eval_infix(r#"T + "x""#).unwrap_err().to_string(),
+            "'+' expected string operand, found boolean in 'T'"
+        );
+        assert!(matches!(
+            eval_infix(r#""ab" * 2"#),
+            Err(EvalError::TypeMismatch {
+                expected: Type::Integer,
+                found: Type::String,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn string_comparisons() {
+        let cases = [
+            (r#""abc" = "abc""#, true),
+            (r#""abc" != "abd""#, true),
+            (r#""abc" < "abd""#, true),
+            (r#""ab" < "abc""#, true),
+            (r#""B" < "a""#, true),
+            (r#""b" >= "abc""#, true),
+            (r#""" <= """#, true),
+            (r#""abc" > "abc""#, false),
+            // Values of different types are never equal
+            (r#""1" = 1"#, false),
+            (r#""T" != T"#, true),
+        ];
+        for (program, expected) in cases {
+            assert_eq!(
+                eval_infix(program),
+                Ok(Value::Bool(expected)),
+                "{}",
+                program
+            );
+        }
+        assert_eq!(
+            eval_infix(r#""10" < 9"#),
+            Err(EvalError::TypeMismatch {
+                op: Operator::Binary(BinaryOperator::LessThan),
+                expected: Type::String,
+                found: Type::Integer,
+                expr: Expression::Integer(int(9)),
+                span: None,
+            })
+        );
+    }
+
+    #[test]
+    fn len_counts_characters() {
+        assert_eq!(eval_infix(r#"len("hello")"#), Ok(Value::Int(int(5))));
+        assert_eq!(eval_infix(r#"len("")"#), Ok(Value::Int(int(0))));
+        assert_eq!(eval_infix(r#"len("héllo\n")"#), Ok(Value::Int(int(6))));
+        assert_eq!(
+            eval_infix("len(12)"),
+            Err(EvalError::TypeMismatch {
+                op: Operator::Builtin(Builtin::Len),
+                expected: Type::String,
+                found: Type::Integer,
+                expr: Expression::Integer(int(12)),
+                span: None,
+            })
+        );
+    }
+
+    #[test]
+    fn substring() {
+        let cases = [
+            (r#"substring("hello", 1, 3)"#, "el"),
+            (r#"substring("hello", 0, 5)"#, "hello"),
+            (r#"substring("hello", 2, 2)"#, ""),
+            (r#"substring("héllo", 1, 2)"#, "é"),
+            // Indices are clamped to the string
+            (r#"substring("hello", -2, 2)"#, "he"),
+            (r#"substring("hello", 3, 99)"#, "lo"),
+            (r#"substring("hello", 9, 12)"#, ""),
+            (r#"substring("hello", 4, 1)"#, ""),
+        ];
+        for (program, expected) in cases {
+            assert_eq!(eval_infix(program), Ok(string(expected)), "{}", program);
+        }
+        assert!(matches!(
+            eval_infix(r#"substring("hello", "1", 3)"#),
+            Err(EvalError::TypeMismatch {
+                op: Operator::Builtin(Builtin::Substring),
+                expected: Type::Integer,
+                found: Type::String,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn to_string_writes_any_value() {
+        let cases = [
+            ("to_string(42)", "42"),
+            ("to_string(-7)", "-7"),
+            ("to_string(T)", "T"),
+            (r#"to_string("already")"#, "already"),
+            ("to_string(2.5)", "2.5"),
+            ("to_string(func x => x + 1)", "func x => x + 1"),
+        ];
+        for (program, expected) in cases {
+            assert_eq!(eval_infix(program), Ok(string(expected)), "{}", program);
+        }
+        assert_eq!(
+            eval_infix_with("to_string(7 / 2)", Mode::Exact),
+            Ok(string("7/2"))
+        );
+    }
+
+    #[test]
+    fn parse_int() {
+        assert_eq!(eval_infix(r#"parse_int("42")"#), Ok(Value::Int(int(42))));
+        assert_eq!(
+            eval_infix(r#"parse_int(" -17\n")"#),
+            Ok(Value::Int(int(-17)))
+        );
+        assert_eq!(
+            eval_infix(r#"parse_int(to_string(123)) + 1"#),
+            Ok(Value::Int(int(124)))
+        );
+        assert_eq!(
+            eval_infix(r#"parse_int("4x2")"#),
+            Err(EvalError::InvalidInteger {
+                text: "4x2".to_string(),
+                expr: Expression::String("4x2".to_string()),
+                span: None,
+            })
+        );
+        assert_eq!(
+            eval_infix(r#"parse_int("")"#).unwrap_err().to_string(),
+            r#"cannot parse "" as an integer in '""'"#
+        );
+        assert!(matches!(
+            eval_infix("parse_int(42)"),
+            Err(EvalError::TypeMismatch {
+                expected: Type::String,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    #[cfg(not(feature = "bigint"))]
+    fn parse_int_out_of_range() {
+        assert!(matches!(
+            eval_infix(r#"parse_int("9223372036854775808")"#),
+            Err(EvalError::InvalidInteger { .. })
+        ));
+    }
+
+    #[test]
+    fn string_errors_carry_spans() {
+        let program = r#"1 + len(parse_int("x"))"#;
+        let (expr, spans) = Parser::new(program)
+            .with_syntax(Syntax::Infix)
+            .parse_spanned()
+            .unwrap();
+        let error = Evaluator::new().eval_spanned(&expr, &spans).unwrap_err();
+        assert_eq!(error.span(), Some(Span::new(18, 21)));
+    }
+
+    #[test]
+    fn string_values() {
+        let bindings = Bindings::new()
+            .with("name", "Ada")
+            .with("greeting", String::from("Hi "));
+        assert_eq!(
+            parse_infix("greeting + name").eval_with(&bindings),
+            Ok(string("Hi Ada"))
+        );
+        let value = string("a \"quoted\"\nline");
+        assert_eq!(value.type_of(), Type::String);
+        assert_eq!(value.as_str(), Some("a \"quoted\"\nline"));
+        assert_eq!(Value::Int(int(1)).as_str(), None);
+        assert_eq!(value.to_string(), "a \"quoted\"\nline");
+        assert_eq!(
+            value.to_expression(),
+            Expression::String("a \"quoted\"\nline".to_string())
+        );
+        assert_eq!(
+            format!("{}", value.to_expression()),
+            r#""a \"quoted\"\nline""#
+        );
+        assert_eq!(String::try_from(string("ok")), Ok("ok".to_string()));
+        assert_eq!(
+            String::try_from(Value::Bool(true)).unwrap_err().to_string(),
+            "expected string, found boolean 'T'"
+        );
+        // Closures capture strings like any other value
+        let closure = eval_infix(r#"let suffix = "!" in func s => s + suffix"#).unwrap();
+        assert_eq!(closure.to_string(), r#"func s => s + "!""#);
+    }
+}
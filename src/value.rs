@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Error};
+use std::rc::Rc;
+
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::{Decimal, RoundingStrategy};
+
+use crate::error::{ConversionError, Type};
+use crate::evaluator::Node;
+use crate::expression::{substitute, BinaryOperator, Expression};
+use crate::integer::{self, Int};
+use crate::rational::Rational;
+
+/// The result of evaluating an expression.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    Int(Int),
+    /// A fraction that is not a whole number, from exact division.
+    Rational(Rational),
+    /// A fixed-point decimal, from a float literal under [`Floats::Decimal`](crate::evaluator::Floats::Decimal).
+    Decimal(Decimal),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Closure(Closure),
+    /// An unbound variable, left as is under [`Unbound::Symbolic`](crate::evaluator::Unbound::Symbolic).
+    Symbol(String),
+}
+
+/// A function together with the environment it was created in.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Closure {
+    pub(crate) param: Rc<str>,
+    pub(crate) body: Rc<Node>,
+    pub(crate) env: Env,
+    // The name a `let rec` function refers to itself by, bound to the closure
+    // each time it is applied
+    pub(crate) name: Option<Rc<str>>,
+}
+
+/// The variables in scope during evaluation, innermost first. Extending an
+/// environment shares the outer one rather than copying it, so closures can
+/// capture it cheaply.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Env(Option<Rc<Binding>>);
+
+#[derive(Debug, PartialEq)]
+struct Binding {
+    name: Rc<str>,
+    value: Value,
+    next: Env,
+}
+
+/// Values for variables that are free in an expression, such as the inputs of a formula.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Bindings(HashMap<String, Value>);
+
+impl Bindings {
+    pub fn new() -> Self {
+        Bindings(HashMap::new())
+    }
+
+    /// Binds `name` to `value`, returning the value it was previously bound to.
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<Value>) -> Option<Value> {
+        self.0.insert(name.into(), value.into())
+    }
+
+    /// Binds `name` to `value`, for building bindings in a single expression.
+    pub fn with(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.insert(name, value);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.0.get(name)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        self.0.remove(name)
+    }
+}
+
+impl<K: Into<String>, V: Into<Value>> FromIterator<(K, V)> for Bindings {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Bindings(
+            iter.into_iter()
+                .map(|(name, value)| (name.into(), value.into()))
+                .collect(),
+        )
+    }
+}
+
+impl Env {
+    pub fn new() -> Self {
+        Env(None)
+    }
+
+    /// Returns a new environment where `name` is bound to `value`, shadowing any outer binding.
+    pub fn bind(&self, name: impl Into<Rc<str>>, value: Value) -> Env {
+        Env(Some(Rc::new(Binding {
+            name: name.into(),
+            value,
+            next: self.clone(),
+        })))
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&Value> {
+        let mut env = self;
+        while let Some(binding) = &env.0 {
+            if &*binding.name == name {
+                return Some(&binding.value);
+            }
+            env = &binding.next;
+        }
+        None
+    }
+}
+
+impl Value {
+    pub fn type_of(&self) -> Type {
+        match self {
+            Value::Int(_) => Type::Integer,
+            Value::Rational(_) => Type::Rational,
+            Value::Decimal(_) => Type::Decimal,
+            Value::Float(_) => Type::Float,
+            Value::Bool(_) => Type::Boolean,
+            Value::String(_) => Type::String,
+            Value::Closure(_) => Type::Function,
+            Value::Symbol(_) => Type::Variable,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<&Int> {
+        match self {
+            Value::Int(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The value as an `i64`, if it is an integer that fits in one.
+    pub fn as_i64(&self) -> Option<i64> {
+        self.as_int().and_then(integer::to_i64)
+    }
+
+    pub fn as_rational(&self) -> Option<&Rational> {
+        match self {
+            Value::Rational(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_decimal(&self) -> Option<Decimal> {
+        match self {
+            Value::Decimal(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The float nearest to the value, if it is a number of any kind.
+    pub fn to_f64(&self) -> Option<f64> {
+        match self {
+            Value::Int(value) => Some(integer::to_f64(value)),
+            Value::Rational(value) => Some(value.to_f64()),
+            Value::Decimal(value) => value.to_f64(),
+            Value::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_closure(&self) -> Option<&Closure> {
+        match self {
+            Value::Closure(closure) => Some(closure),
+            _ => None,
+        }
+    }
+
+    /// Converts the value back into an expression that evaluates to it. The
+    /// captured environment of a closure is substituted into its body.
+    pub fn to_expression(&self) -> Expression {
+        match self {
+            Value::Int(value) => Expression::Integer(integer::dup(value)),
+            Value::Rational(value) => Expression::BinaryOp {
+                op: BinaryOperator::Divide,
+                lhs: Box::new(Expression::Integer(integer::dup(value.numer()))),
+                rhs: Box::new(Expression::Integer(integer::dup(value.denom()))),
+            },
+            // A float literal with the decimal's own digits, which Floats::Decimal reads exactly
+            Value::Decimal(value) => {
+                let mut text = value.to_string();
+                if value.scale() == 0 {
+                    text.push_str(".0");
+                }
+                Expression::Float {
+                    value: value.to_f64().unwrap_or(f64::NAN),
+                    text,
+                }
+            }
+            Value::Float(value) => Expression::float(*value),
+            Value::Bool(value) => Expression::Boolean(*value),
+            Value::String(text) => Expression::String(text.clone()),
+            Value::Closure(closure) => closure.to_expression(),
+            Value::Symbol(name) => Expression::Variable(name.clone()),
+        }
+    }
+}
+
+impl Closure {
+    pub fn param(&self) -> &str {
+        &self.param
+    }
+
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+
+    /// The name the function refers to itself by, if it was defined with `let rec`.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The function this closure was created from, with its free variables
+    /// replaced by their values from the captured environment. A recursive
+    /// function becomes `let rec name = func ... in name`.
+    pub fn to_expression(&self) -> Expression {
+        let func = match &self.name {
+            Some(name) => Expression::LetRec {
+                name: name.to_string(),
+                param: self.param.to_string(),
+                func_body: Box::new(self.body.to_expression()),
+                body: Box::new(Expression::Variable(name.to_string())),
+            },
+            None => Expression::Func {
+                param: self.param.to_string(),
+                body: Box::new(self.body.to_expression()),
+            },
+        };
+        let replacements: HashMap<String, Expression> = func
+            .free_variables()
+            .into_iter()
+            .filter_map(|name| {
+                let value = self.env.lookup(&name)?.to_expression();
+                Some((name, value))
+            })
+            .collect();
+        substitute(&func, &replacements)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match self {
+            // Numbers take a precision, as in "{:.2}", to be written as decimals
+            Value::Int(value) if f.precision().is_some() => {
+                Display::fmt(&Rational::from(integer::dup(value)), f)
+            }
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Rational(value) => Display::fmt(value, f),
+            // Rounded here, since rust_decimal rounds halves to even, unlike fractions
+            Value::Decimal(value) => match f.precision() {
+                Some(places) => {
+                    let strategy = RoundingStrategy::MidpointAwayFromZero;
+                    let rounded = value.round_dp_with_strategy(places as u32, strategy);
+                    write!(f, "{:.*}", places, rounded)
+                }
+                None => Display::fmt(value, f),
+            },
+            Value::Float(value) if f.precision().is_some() => Display::fmt(value, f),
+            // Debug keeps the point in whole floats, so they do not look like integers
+            Value::Float(value) => write!(f, "{:?}", value),
+            Value::Bool(value) => write!(f, "{}", if *value { "T" } else { "F" }),
+            // Strings are written as their text, without quotes or escapes
+            Value::String(text) => write!(f, "{}", text),
+            Value::Closure(closure) => write!(f, "{}", closure.to_expression()),
+            Value::Symbol(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Int(integer::int(value))
+    }
+}
+
+#[cfg(feature = "bigint")]
+impl From<Int> for Value {
+    fn from(value: Int) -> Self {
+        Value::Int(value)
+    }
+}
+
+// Whole numbers become integers, so they compare equal to integers
+impl From<Rational> for Value {
+    fn from(value: Rational) -> Self {
+        if value.is_integer() {
+            Value::Int(integer::dup(value.numer()))
+        } else {
+            Value::Rational(value)
+        }
+    }
+}
+
+impl From<Decimal> for Value {
+    fn from(value: Decimal) -> Self {
+        Value::Decimal(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Float(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+// Fails for integers that do not fit, as well as for other types
+impl TryFrom<Value> for i64 {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value.as_i64() {
+            Some(value) => Ok(value),
+            None => Err(ConversionError {
+                expected: Type::Integer,
+                value,
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "bigint")]
+impl TryFrom<Value> for Int {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Int(value) => Ok(value),
+            _ => Err(ConversionError {
+                expected: Type::Integer,
+                value,
+            }),
+        }
+    }
+}
+
+// Integers convert to whole fractions
+impl TryFrom<Value> for Rational {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Int(value) => Ok(Rational::from(value)),
+            Value::Rational(value) => Ok(value),
+            _ => Err(ConversionError {
+                expected: Type::Rational,
+                value,
+            }),
+        }
+    }
+}
+
+// Integers convert to whole decimals, if they fit
+impl TryFrom<Value> for Decimal {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let decimal = match &value {
+            Value::Decimal(value) => Some(*value),
+            Value::Int(value) => integer::to_decimal(value),
+            _ => None,
+        };
+        decimal.ok_or(ConversionError {
+            expected: Type::Decimal,
+            value,
+        })
+    }
+}
+
+// Numbers of any kind convert to the nearest float
+impl TryFrom<Value> for f64 {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        value.to_f64().ok_or(ConversionError {
+            expected: Type::Float,
+            value,
+        })
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Bool(value) => Ok(value),
+            _ => Err(ConversionError {
+                expected: Type::Boolean,
+                value,
+            }),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(text) => Ok(text),
+            _ => Err(ConversionError {
+                expected: Type::String,
+                value,
+            }),
+        }
+    }
+}